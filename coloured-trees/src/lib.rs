@@ -1,26 +1,35 @@
 use itertools::Itertools;
 use rmq::Rmq;
-use std::{cmp::max, collections::HashMap};
+use std::{cmp::max, collections::HashMap, fmt::Debug, hash::Hash};
 
 type Node = usize;
 type Weight = usize;
 
+/// `I` is the integer type of labels and LCPs, e.g. `u32` for compact trees.
 #[derive(Debug)]
-pub struct Tree {
+pub struct Tree<I = usize> {
     /// Leaf labels.
-    pub sa: Vec<Node>,
+    pub sa: Vec<I>,
     /// LCPs between adjacent leafs.
     /// NOTE: This must have the same length as leafs and be end-padded with a 0.
-    pub lcp: Vec<Weight>,
+    pub lcp: Vec<I>,
+}
+
+/// Bounds on the integer type of a `Tree`.
+pub trait TreeIdx: Copy + Ord + Default + Hash + Debug + TryInto<usize> {}
+impl<I: Copy + Ord + Default + Hash + Debug + TryInto<usize>> TreeIdx for I {}
+
+fn weight<I: TreeIdx>(w: I) -> Weight {
+    w.try_into().ok().unwrap()
 }
 
 /// Find a pair of nodes (u, v) such that LCP_a(u, v) + LCP_b(u, v) is maximized.
 /// The trees must have the same set of nodes.
-pub fn max_common_weight(a: &Tree, b: &Tree) -> (Weight, (Node, Node)) {
+pub fn max_common_weight<I: TreeIdx>(a: &Tree<I>, b: &Tree<I>) -> (Weight, (Node, Node)) {
     assert_eq!(a.lcp.len(), a.sa.len());
-    assert_eq!(a.lcp.last(), Some(&0));
+    assert_eq!(a.lcp.last(), Some(&I::default()));
     assert_eq!(b.lcp.len(), b.sa.len());
-    assert_eq!(b.lcp.last(), Some(&0));
+    assert_eq!(b.lcp.last(), Some(&I::default()));
     assert_eq!(a.sa.len(), b.sa.len());
 
     let mut b_idx = get_permutation(a, b);
@@ -30,7 +39,7 @@ pub fn max_common_weight(a: &Tree, b: &Tree) -> (Weight, (Node, Node)) {
     let rmq = rmq::MaskRmq::new(&b.lcp);
 
     // Inclusive start pos in a of range of subtree, and right-lcp.
-    let mut stack = vec![(0, 0)];
+    let mut stack = vec![(0, I::default())];
     for (i, &a_lcp_right) in a.lcp.iter().enumerate() {
         let mut start = i;
         while !stack.is_empty() && stack.last().unwrap().1 >= a_lcp_right {
@@ -45,7 +54,7 @@ pub fn max_common_weight(a: &Tree, b: &Tree) -> (Weight, (Node, Node)) {
             for (&(bl, al), &(br, ar)) in b_idx[old_start..=i].iter().tuple_windows() {
                 if (al < start) ^ (ar < start) {
                     let b_lcp = rmq.query(bl..br);
-                    ans = max(ans, (a_lcp + b_lcp, (al, ar)));
+                    ans = max(ans, (weight(a_lcp) + weight(b_lcp), (al, ar)));
                 }
             }
 
@@ -59,8 +68,8 @@ pub fn max_common_weight(a: &Tree, b: &Tree) -> (Weight, (Node, Node)) {
 
 /// Find the permutation from a nodes to b nodes.
 /// Returns a vec of (b_idx, a_idx) pairs.
-fn get_permutation(a: &Tree, b: &Tree) -> Vec<(usize, usize)> {
-    let mut b_inv: HashMap<usize, usize> = HashMap::new();
+fn get_permutation<I: TreeIdx>(a: &Tree<I>, b: &Tree<I>) -> Vec<(usize, usize)> {
+    let mut b_inv: HashMap<I, usize> = HashMap::new();
     for (i, &node) in b.sa.iter().enumerate() {
         b_inv.insert(node, i);
     }
@@ -108,6 +117,30 @@ mod test {
         max_common_weight(&a, &b);
     }
 
+    #[test]
+    fn compact() {
+        let a = Tree::<u32> {
+            sa: vec![0, 1, 2, 3, 4, 5, 6, 7],
+            lcp: vec![2, 3, 1, 4, 2, 1, 3, 0],
+        };
+        let b = Tree::<u32> {
+            sa: vec![7, 6, 5, 4, 3, 2, 1, 0],
+            lcp: vec![0, 1, 3, 1, 1, 2, 1, 0],
+        };
+        let a_usize = Tree {
+            sa: a.sa.iter().map(|&x| x as usize).collect(),
+            lcp: a.lcp.iter().map(|&x| x as usize).collect(),
+        };
+        let b_usize = Tree {
+            sa: b.sa.iter().map(|&x| x as usize).collect(),
+            lcp: b.lcp.iter().map(|&x| x as usize).collect(),
+        };
+        assert_eq!(
+            max_common_weight(&a, &b),
+            max_common_weight(&a_usize, &b_usize)
+        );
+    }
+
     #[test]
     fn random() {
         for n in 1..300 {
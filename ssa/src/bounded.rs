@@ -0,0 +1,119 @@
+//! Sorting sampled suffixes by a bounded number of characters, e.g. for seeding.
+//! Groups of suffixes are not refined once they share `max_depth` characters,
+//! which saves most of the work on repetitive texts.
+use std::ops::Range;
+
+use crate::{
+    builder::BuildStats, hasher_sampling, rolling_hash::RollingHash, EndOfText, Idx, Ssa, Suffixes,
+};
+
+/// Sampled suffixes sorted by their first `max_depth` characters, as built by `Ssa::new_bounded`.
+pub struct BoundedSsa<I = usize> {
+    /// LCPs are capped at `max_depth`. Suffixes within a tie group are sorted by position.
+    pub ssa: Ssa<I>,
+    pub max_depth: usize,
+    /// The ranges of `ssa.sa` whose suffixes share their first `max_depth` characters.
+    /// Each contains at least two suffixes.
+    pub ties: Vec<Range<usize>>,
+}
+
+impl<I: Idx> Ssa<I> {
+    /// Sort the suffixes of `t` starting at `idxs` by their first `max_depth` characters only.
+    /// Suffixes shorter than `max_depth` sort before their extensions, as in `new`.
+    /// Runs on `threads` threads; `0` uses all cores.
+    pub fn new_bounded(t: &[u8], idxs: &[I], max_depth: usize, threads: usize) -> BoundedSsa<I> {
+        let hasher = RollingHash::new(t, hasher_sampling(t.len()));
+        let mut ssa = Self::new_bounded_with_hasher(
+            t,
+            Suffixes::Text(EndOfText::Smallest),
+            idxs,
+            Some(idxs.len().ilog2() as _),
+            true,
+            threads,
+            &hasher,
+            max_depth,
+            &mut BuildStats::default(),
+        );
+
+        // `lcp[i]` is between `sa[i]` and `sa[i + 1]`, so a maximal run of capped LCPs `a..b` is a tie `a..b + 1`.
+        let mut ties = vec![];
+        let mut start = 0;
+        for i in 0..=ssa.lcp.len() {
+            if i == ssa.lcp.len() || ssa.lcp[i].as_usize() < max_depth {
+                if i > start {
+                    ties.push(start..i + 1);
+                }
+                start = i + 1;
+            }
+        }
+        // The order of ties depends on the hashes and threads, so fix it.
+        for tie in &ties {
+            ssa.sa[tie.clone()].sort_unstable();
+        }
+        BoundedSsa {
+            ssa,
+            max_depth,
+            ties,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn check(t: &[u8], idxs: &[usize], max_depth: usize) {
+        let prefix = |i: usize| &t[i..(i + max_depth).min(t.len())];
+        let full = Ssa::new(t, idxs);
+        for threads in [1, 4] {
+            let bounded = Ssa::new_bounded(t, idxs, max_depth, threads);
+            let ssa = &bounded.ssa;
+            assert!(ssa
+                .sa
+                .iter()
+                .map(|&i| prefix(i))
+                .eq(full.sa.iter().map(|&i| prefix(i))));
+            assert!(ssa.lcp.iter().eq(full
+                .lcp
+                .iter()
+                .map(|&l| l.min(max_depth))
+                .collect::<Vec<_>>()
+                .iter()));
+            let mut ties: Vec<Range<usize>> = vec![];
+            for (i, w) in ssa.sa.windows(2).enumerate() {
+                if prefix(w[0]) == prefix(w[1]) {
+                    assert!(w[0] < w[1]);
+                    match ties.last_mut() {
+                        Some(tie) if tie.end == i + 1 => tie.end = i + 2,
+                        _ => ties.push(i..i + 2),
+                    }
+                }
+            }
+            assert_eq!(bounded.ties, ties, "max_depth {max_depth}");
+        }
+    }
+
+    #[test]
+    fn bounded() {
+        for len in [1, 10, 100, 1000, 10000] {
+            let t = &(0..len)
+                .map(|_| rand::random::<u8>() % 4)
+                .collect::<Vec<_>>();
+            let idxs = (0..len)
+                .filter(|_| rand::random::<f64>() < 0.5)
+                .collect::<Vec<usize>>();
+            if idxs.is_empty() {
+                continue;
+            }
+            for max_depth in [0, 1, 3, 8, 100] {
+                check(t, &idxs, max_depth);
+            }
+        }
+        // Repetitive input, where most suffixes tie.
+        let t = &b"ACGTTGCA".repeat(2000);
+        let idxs = (0..t.len()).step_by(3).collect::<Vec<_>>();
+        for max_depth in [5, 16, 31, 1000] {
+            check(t, &idxs, max_depth);
+        }
+    }
+}
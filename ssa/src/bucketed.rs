@@ -0,0 +1,224 @@
+//! Construction of an SSA file under a memory budget.
+//!
+//! The sampled positions are partitioned into buckets by the hash of their first `l0` characters.
+//! Buckets are ordered by their prefix, and consecutive buckets are grouped into batches that fit in the budget.
+//! The positions are partitioned by batch in a single pass, and each batch is sorted on its own. Its SA is appended to the output file,
+//! and its LCPs are spilled to a temporary file that is appended once all batches are done.
+use std::{
+    cmp::min,
+    collections::HashMap,
+    fs::{self, File},
+    io::{self, BufWriter, Write},
+    mem::{size_of, size_of_val},
+    path::Path,
+};
+
+use crate::{
+    builder::BuildStats,
+    file::{as_bytes, Header, Meta},
+    hasher_sampling, index_fits, lcp,
+    rolling_hash::{Mod, RollingHash},
+    EndOfText, Idx, Ssa, Suffixes, IH,
+};
+
+/// Estimated peak memory used to sort a single suffix:
+/// its hash record, its SA and LCP entries, and its share of the group cache.
+pub fn bytes_per_suffix<I: Idx>() -> usize {
+    size_of::<IH<I>>() + 4 * size_of::<I>()
+}
+
+/// Estimated memory of the bucket map and the sorted list of buckets, per bucket.
+fn bytes_per_bucket() -> usize {
+    // A map entry and its control byte, and a list entry.
+    2 * size_of::<(Mod, (usize, usize))>() + 1
+}
+
+/// Build the SSA of `t` on `idxs` and write it to `path` in the format of `Ssa::save`.
+/// Uses about `budget` bytes besides the text and `idxs`, including the buckets
+/// and a copy of `idxs` partitioned by batch.
+/// When these alone exceed the budget, or a single bucket does, each bucket is sorted on its own.
+/// Each batch is sorted on `threads` threads; `0` uses all cores.
+/// Like the hash-based sorting itself, buckets are correct with high probability: prefixes with colliding
+/// hashes would share a bucket and may end up in the wrong batch. Debug builds check for this.
+/// On error, the partial output file is removed.
+pub fn build_bucketed<I: Idx>(
+    t: &[u8],
+    idxs: &[I],
+    l0: usize,
+    budget: usize,
+    threads: usize,
+    path: &Path,
+    meta: &Meta,
+) -> io::Result<Header> {
+    assert!(!idxs.is_empty());
+    let n = t.len();
+    assert!(
+        index_fits::<I>(n, idxs.len()),
+        "Text too long for index type."
+    );
+    // Prefixes running past the end of the text get a unique sentinel hash.
+    let l0 = l0.min(n);
+    let hasher = RollingHash::new(t, hasher_sampling(n));
+    let bucket = |i: I| {
+        let i = i.as_usize();
+        hasher.query(i..i + l0)
+    };
+    let prefix = |i: usize| &t[i..min(i + l0, n)];
+
+    // 1. Count the suffixes in each bucket, and keep one of them as representative.
+    // The map later stores the batch of each bucket instead of its size.
+    let mut buckets: HashMap<Mod, (usize, usize)> = HashMap::new();
+    for &i in idxs {
+        buckets.entry(bucket(i)).or_insert((i.as_usize(), 0)).1 += 1;
+    }
+
+    // 2. Sort the buckets by prefix, and group them into batches of what is left of the budget.
+    let fixed = buckets.capacity() * bytes_per_bucket() + size_of_val(idxs);
+    let max_batch = (budget.saturating_sub(fixed) / bytes_per_suffix::<I>()).max(1);
+    let mut sorted = buckets
+        .iter()
+        .map(|(&h, &(rep, size))| (h, rep, size))
+        .collect::<Vec<_>>();
+    sorted.sort_by(|(_, a, _), (_, b, _)| prefix(*a).cmp(prefix(*b)));
+    let mut batch_sizes: Vec<usize> = vec![];
+    for (h, _, size) in sorted {
+        match batch_sizes.last_mut() {
+            Some(last) if *last + size <= max_batch => *last += size,
+            _ => batch_sizes.push(size),
+        }
+        buckets.get_mut(&h).unwrap().1 = batch_sizes.len() - 1;
+    }
+
+    // 3. Partition the positions by batch, hashing each of them once.
+    let mut next = batch_sizes
+        .iter()
+        .scan(0, |end, &size| {
+            let start = *end;
+            *end += size;
+            Some(start)
+        })
+        .collect::<Vec<_>>();
+    let mut partitioned = vec![I::default(); idxs.len()];
+    for &i in idxs {
+        let (rep, batch) = buckets[&bucket(i)];
+        debug_assert_eq!(
+            prefix(i.as_usize()),
+            prefix(rep),
+            "Hash collision between the prefixes at {i:?} and {rep}."
+        );
+        let next = &mut next[batch];
+        partitioned[*next] = i;
+        *next += 1;
+    }
+    drop(buckets);
+    drop(next);
+
+    // 4. Sort each batch, append its SA to the output, and spill its LCPs.
+    let header = Header {
+        meta: *meta,
+        width: size_of::<I>(),
+        len: idxs.len(),
+    };
+    let spill_path = path.with_extension("lcp.tmp");
+    let write = || -> io::Result<()> {
+        let mut out = BufWriter::new(File::create(path)?);
+        out.write_all(&header.encode())?;
+        let mut spill = BufWriter::new(File::create(&spill_path)?);
+        let mut last = None;
+        let mut start = 0;
+        for &size in &batch_sizes {
+            let batch_idxs = &partitioned[start..start + size];
+            start += size;
+            let ssa = Ssa::new_with_hasher(
+                t,
+                Suffixes::Text(EndOfText::Smallest),
+                batch_idxs,
+                Some(size.ilog2() as _),
+                true,
+                threads,
+                &hasher,
+                &mut BuildStats::default(),
+            );
+            if let Some(last) = last {
+                // Suffixes in different buckets differ in their first `l0` characters.
+                let seam = I::from_usize(lcp(t, last, ssa.sa[0].as_usize()));
+                spill.write_all(as_bytes(&[seam]))?;
+            }
+            spill.write_all(as_bytes(&ssa.lcp))?;
+            out.write_all(as_bytes(&ssa.sa))?;
+            last = Some(ssa.sa[size - 1].as_usize());
+        }
+        spill.flush()?;
+        drop(spill);
+
+        // 5. Append the LCP array.
+        io::copy(&mut File::open(&spill_path)?, &mut out)?;
+        out.flush()?;
+        fs::remove_file(&spill_path)
+    };
+    write().inspect_err(|_| {
+        // Do not leave the spilled LCPs or a partial output behind. Either may not exist.
+        let _ = fs::remove_file(&spill_path);
+        let _ = fs::remove_file(path);
+    })?;
+    Ok(header)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::file::{MappedSsa, Sampling};
+
+    fn check<I: Idx>(t: &[u8], idxs: &[I], l0: usize, budget: usize) {
+        let path = std::env::temp_dir().join(format!(
+            "ssa-{}-bucketed-{}-{budget}",
+            std::process::id(),
+            size_of::<I>()
+        ));
+        let meta = Meta::new(t, Sampling::Explicit, Some(l0));
+        let header = build_bucketed(t, idxs, l0, budget, 2, &path, &meta).unwrap();
+        assert_eq!(header, Header::read(&path).unwrap());
+        let mapped = MappedSsa::<I>::open(&path).unwrap();
+        let ssa = Ssa::new(t, idxs);
+        assert_eq!(mapped.sa(), &ssa.sa[..]);
+        assert_eq!(mapped.lcp(), &ssa.lcp[..]);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn bucketed() {
+        for len in [1, 10, 100, 1000, 10000] {
+            let t = &(0..len)
+                .map(|_| rand::random::<u8>() % 4)
+                .collect::<Vec<_>>();
+            let idxs = (0..len)
+                .filter(|_| rand::random::<f64>() < 0.5)
+                .collect::<Vec<usize>>();
+            if idxs.is_empty() {
+                continue;
+            }
+            for budget in [0, 1000, 100000, usize::MAX] {
+                check(t, &idxs, 4, budget);
+            }
+            let idxs = idxs.iter().map(|&i| i as u32).collect::<Vec<_>>();
+            check(t, &idxs, 3, 1000);
+        }
+        // A single bucket larger than the budget.
+        let t = &vec![0; 1000];
+        check(t, &(0..t.len()).collect::<Vec<_>>(), 8, 100);
+    }
+
+    #[test]
+    fn cleanup() {
+        let path =
+            std::env::temp_dir().join(format!("ssa-{}-bucketed-cleanup", std::process::id()));
+        // Creating the spill file fails after the output file was created.
+        let spill_path = path.with_extension("lcp.tmp");
+        std::fs::create_dir_all(&spill_path).unwrap();
+        let t = b"abracadabra";
+        let meta = Meta::new(t, Sampling::Explicit, Some(2));
+        assert!(build_bucketed(t, &[0usize, 3, 7], 2, 1000, 1, &path, &meta).is_err());
+        assert!(!path.exists());
+        std::fs::remove_dir(&spill_path).unwrap();
+    }
+}
@@ -0,0 +1,562 @@
+use std::{
+    fmt,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    dense::DENSE_THRESHOLD, hasher_sampling, index_fits, rolling_hash::RollingHash,
+    verify::Failure, EndOfText, Idx, Ssa, Suffixes,
+};
+
+/// Reasons why an `Ssa` can not be built.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SsaError {
+    /// No positions were given.
+    Empty,
+    /// Position `idx` is not a suffix of the text of length `n`.
+    OutOfRange { idx: usize, n: usize },
+    /// Position `idx` was given more than once.
+    Duplicate { idx: usize },
+    /// A text of length `n` with `b` sampled suffixes does not fit in the index type.
+    TooLong { n: usize, b: usize },
+    /// `l0` rounded up to a power of two exceeds the text length `n`.
+    InvalidL0 { l0: usize, n: usize },
+    /// The hasher sampling rate `s` is not a power of two of at least 8.
+    InvalidSampling { s: usize },
+    /// The given hasher was built on a different text.
+    HasherMismatch,
+    /// Deterministic construction only supports `EndOfText::Smallest`.
+    DeterministicEnd,
+    /// Deterministic construction does not support sorting prefixes.
+    DeterministicPrefixes,
+    /// The suffixes given to `Ssa::from_sorted_checked` are not in increasing order,
+    /// first at this pair.
+    NotSorted(Failure),
+}
+
+impl fmt::Display for SsaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SsaError::Empty => write!(f, "No positions to sort."),
+            SsaError::OutOfRange { idx, n } => {
+                write!(
+                    f,
+                    "Position {idx} is out of range for a text of length {n}."
+                )
+            }
+            SsaError::Duplicate { idx } => write!(f, "Position {idx} is given more than once."),
+            SsaError::TooLong { n, b } => write!(
+                f,
+                "A text of length {n} with {b} positions is too long for the index type."
+            ),
+            SsaError::InvalidL0 { l0, n } => {
+                write!(f, "l0 = {l0} is too large for a text of length {n}.")
+            }
+            SsaError::InvalidSampling { s } => {
+                write!(f, "Sampling rate {s} is not a power of two of at least 8.")
+            }
+            SsaError::HasherMismatch => write!(f, "The hasher was built on a different text."),
+            SsaError::DeterministicEnd => write!(
+                f,
+                "Deterministic construction requires the end of the text to be smallest."
+            ),
+            SsaError::DeterministicPrefixes => {
+                write!(f, "Deterministic construction can not sort prefixes.")
+            }
+            SsaError::NotSorted(Failure { i, sa: (a, b), .. }) => write!(
+                f,
+                "The suffixes at {a} and {b} at ranks {i} and {} are not in increasing order.",
+                i + 1
+            ),
+        }
+    }
+}
+
+impl std::error::Error for SsaError {}
+
+/// Counters and timings of a single construction, returned by `SsaBuilder::build`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BuildStats {
+    /// Whether the full suffix array was used instead of hashing. See `SsaBuilder::dense_threshold`.
+    pub dense: bool,
+    /// The number of hash queries issued while grouping.
+    pub hash_queries: usize,
+    /// The number of groups that were ordered by the period of their common prefix.
+    pub periodic_groups: usize,
+    /// The maximal recursion depth, starting at 0.
+    pub max_depth: usize,
+    /// The number of hash groups found at each recursion depth.
+    pub groups_per_level: Vec<usize>,
+    /// The maximal length of the group cache. With multiple threads, the maximum over all threads.
+    pub cache_peak: usize,
+    /// Time to build the hasher. Zero for a prebuilt hasher.
+    pub hasher_time: Duration,
+    /// Time to sort the suffixes and compute the LCP array.
+    pub sort_time: Duration,
+    /// Time of the entire `build`, including input validation.
+    pub total_time: Duration,
+}
+
+impl BuildStats {
+    pub(crate) fn add_groups(&mut self, depth: usize, num_groups: usize) {
+        if self.groups_per_level.len() <= depth {
+            self.groups_per_level.resize(depth + 1, 0);
+        }
+        self.groups_per_level[depth] += num_groups;
+    }
+
+    /// Combine the counters of two independent parts of a construction.
+    pub(crate) fn merge(&mut self, other: &Self) {
+        self.dense |= other.dense;
+        self.hash_queries += other.hash_queries;
+        self.periodic_groups += other.periodic_groups;
+        self.max_depth = self.max_depth.max(other.max_depth);
+        for (depth, &num_groups) in other.groups_per_level.iter().enumerate() {
+            self.add_groups(depth, num_groups);
+        }
+        self.cache_peak = self.cache_peak.max(other.cache_peak);
+        self.hasher_time += other.hasher_time;
+        self.sort_time += other.sort_time;
+        self.total_time += other.total_time;
+    }
+}
+
+/// Fallible construction of an `Ssa`.
+/// The defaults are those of `Ssa::new`.
+pub struct SsaBuilder<'t, I = usize> {
+    t: &'t [u8],
+    idxs: Vec<I>,
+    /// `None` uses `log2(b)`.
+    l0: Option<Option<usize>>,
+    exp_search: bool,
+    threads: usize,
+    /// `None` uses `hasher_sampling(n)`.
+    sampling: Option<usize>,
+    hasher: Option<&'t RollingHash<'t>>,
+    end: EndOfText,
+    dense_threshold: f64,
+    deterministic: bool,
+    prefixes: bool,
+}
+
+impl<'t, I: Idx> SsaBuilder<'t, I> {
+    pub fn new(t: &'t [u8]) -> Self {
+        Self {
+            t,
+            idxs: vec![],
+            l0: None,
+            exp_search: true,
+            threads: 1,
+            sampling: None,
+            hasher: None,
+            end: EndOfText::Smallest,
+            dense_threshold: DENSE_THRESHOLD,
+            deterministic: false,
+            prefixes: false,
+        }
+    }
+    /// Add positions to sort, in any order.
+    pub fn positions(mut self, idxs: impl IntoIterator<Item = I>) -> Self {
+        self.idxs.extend(idxs);
+        self
+    }
+    /// See `Ssa::new_params`.
+    pub fn l0(mut self, l0: Option<usize>) -> Self {
+        self.l0 = Some(l0);
+        self
+    }
+    /// Use exponential search on the LCP length (the default), or binary search from `l0` down.
+    pub fn exp_search(mut self, exp_search: bool) -> Self {
+        self.exp_search = exp_search;
+        self
+    }
+    /// `0` uses all available cores.
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.threads = threads;
+        self
+    }
+    /// Store a prefix hash every `s` positions. Must be a power of two of at least 8.
+    /// Ignored when a prebuilt hasher is given.
+    pub fn sampling(mut self, s: usize) -> Self {
+        self.sampling = Some(s);
+        self
+    }
+    /// How a suffix is ordered relative to its extensions. Defaults to `EndOfText::Smallest`.
+    pub fn end_of_text(mut self, end: EndOfText) -> Self {
+        self.end = end;
+        self
+    }
+    /// Use `Ssa::new_dense` instead of hashing when at least this fraction of the positions is sampled.
+    /// Defaults to `DENSE_THRESHOLD`. Values above 1 disable this. Only applies to suffixes when the end of the text is smallest.
+    pub fn dense_threshold(mut self, threshold: f64) -> Self {
+        self.dense_threshold = threshold;
+        self
+    }
+    /// Use `Ssa::new_deterministic` instead of hashing, so that the result is always correct.
+    /// Takes precedence over `dense_threshold`.
+    pub fn deterministic(mut self, deterministic: bool) -> Self {
+        self.deterministic = deterministic;
+        self
+    }
+    /// Sort the reverse prefixes ending at the positions instead of the suffixes starting there,
+    /// i.e. the suffixes of the reverse text starting at `n-i`, without reversing the text.
+    /// The SA contains the given positions, and `end_of_text` orders the start of the text.
+    pub fn prefixes(mut self, prefixes: bool) -> Self {
+        self.prefixes = prefixes;
+        self
+    }
+    /// Reuse a hasher built on the same text, e.g. for multiple sets of positions,
+    /// or for both the suffixes and the prefixes.
+    pub fn hasher(mut self, hasher: &'t RollingHash<'t>) -> Self {
+        self.hasher = Some(hasher);
+        self
+    }
+
+    pub fn build(self) -> Result<(Ssa<I>, BuildStats), SsaError> {
+        let start = Instant::now();
+        let n = self.t.len();
+        let mut idxs = self.idxs;
+        let b = idxs.len();
+        check_positions(n, &mut idxs)?;
+        let l0 = self.l0.unwrap_or(Some(b.ilog2() as _));
+        if let Some(l0) = l0 {
+            if l0.checked_next_power_of_two().is_none_or(|p| p > n) {
+                return Err(SsaError::InvalidL0 { l0, n });
+            }
+        }
+        if let Some(s) = self.sampling {
+            if s < 8 || !s.is_power_of_two() {
+                return Err(SsaError::InvalidSampling { s });
+            }
+        }
+        if let Some(hasher) = self.hasher {
+            if hasher.text().as_ptr() != self.t.as_ptr() || hasher.text().len() != n {
+                return Err(SsaError::HasherMismatch);
+            }
+        }
+
+        if self.deterministic && self.end != EndOfText::Smallest {
+            return Err(SsaError::DeterministicEnd);
+        }
+        if self.deterministic && self.prefixes {
+            return Err(SsaError::DeterministicPrefixes);
+        }
+
+        let mut stats = BuildStats::default();
+        if self.deterministic {
+            let ssa = Ssa::new_deterministic(self.t, &idxs);
+            stats.sort_time = start.elapsed();
+            stats.total_time = stats.sort_time;
+            return Ok((ssa, stats));
+        }
+        if self.end == EndOfText::Smallest
+            && !self.prefixes
+            && b as f64 >= self.dense_threshold * n as f64
+        {
+            let ssa = Ssa::new_dense(self.t, &idxs);
+            stats.dense = true;
+            stats.sort_time = start.elapsed();
+            stats.total_time = stats.sort_time;
+            return Ok((ssa, stats));
+        }
+        let owned;
+        let hasher = match self.hasher {
+            Some(hasher) => hasher,
+            None => {
+                let s = self.sampling.unwrap_or_else(|| hasher_sampling(n));
+                owned = RollingHash::new(self.t, s);
+                stats.hasher_time = start.elapsed();
+                &owned
+            }
+        };
+        let ssa = Ssa::new_with_hasher(
+            self.t,
+            if self.prefixes {
+                Suffixes::Prefixes(self.end)
+            } else {
+                Suffixes::Text(self.end)
+            },
+            &idxs,
+            l0,
+            self.exp_search,
+            self.threads,
+            hasher,
+            &mut stats,
+        );
+        stats.total_time = start.elapsed();
+        Ok((ssa, stats))
+    }
+}
+
+impl<I: Idx> Ssa<I> {
+    /// Like `new`, but returns an error for invalid input instead of panicking.
+    pub fn try_new(t: &[u8], idxs: &[I]) -> Result<Self, SsaError> {
+        Ok(SsaBuilder::new(t)
+            .positions(idxs.iter().copied())
+            .build()?
+            .0)
+    }
+}
+
+/// Check that `idxs` are distinct suffixes of a text of length `n` that fit in `I`, and sort them.
+pub(crate) fn check_positions<I: Idx>(n: usize, idxs: &mut [I]) -> Result<(), SsaError> {
+    let b = idxs.len();
+    if b == 0 {
+        return Err(SsaError::Empty);
+    }
+    if !index_fits::<I>(n, b) {
+        return Err(SsaError::TooLong { n, b });
+    }
+    // The empty suffix at `n` is allowed, but only for a non-empty text.
+    if let Some(idx) = idxs.iter().find(|&&i| i.as_usize() > n || n == 0) {
+        return Err(SsaError::OutOfRange {
+            idx: idx.as_usize(),
+            n,
+        });
+    }
+    idxs.sort_unstable();
+    if let Some(w) = idxs.windows(2).find(|w| w[0] == w[1]) {
+        return Err(SsaError::Duplicate {
+            idx: w[0].as_usize(),
+        });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn errors() {
+        let t = b"abracadabra";
+        assert_eq!(Ssa::<usize>::try_new(t, &[]).err(), Some(SsaError::Empty));
+        assert_eq!(
+            Ssa::try_new(t, &[3usize, 12]).err(),
+            Some(SsaError::OutOfRange { idx: 12, n: 11 })
+        );
+        assert_eq!(
+            Ssa::try_new(b"", &[0usize]).err(),
+            Some(SsaError::OutOfRange { idx: 0, n: 0 })
+        );
+        assert_eq!(
+            Ssa::try_new(t, &[5usize, 3, 5]).err(),
+            Some(SsaError::Duplicate { idx: 5 })
+        );
+        // `TooLong` needs a text of 4GB; `index_fits` is tested in `lib.rs`.
+        assert_eq!(
+            SsaBuilder::new(t)
+                .positions([0usize, 1])
+                .l0(Some(12))
+                .build()
+                .err(),
+            Some(SsaError::InvalidL0 { l0: 12, n: 11 })
+        );
+        // Rounding up to a power of two overflows.
+        let l0 = usize::MAX / 2 + 2;
+        assert_eq!(
+            SsaBuilder::new(t)
+                .positions([0usize, 1])
+                .l0(Some(l0))
+                .build()
+                .err(),
+            Some(SsaError::InvalidL0 { l0, n: 11 })
+        );
+        assert_eq!(
+            SsaBuilder::new(t)
+                .positions([0usize, 1])
+                .sampling(12)
+                .build()
+                .err(),
+            Some(SsaError::InvalidSampling { s: 12 })
+        );
+        let other = t.to_vec();
+        let hasher = RollingHash::new(&other, 8);
+        assert_eq!(
+            SsaBuilder::new(t)
+                .positions([0usize, 1])
+                .hasher(&hasher)
+                .build()
+                .err(),
+            Some(SsaError::HasherMismatch)
+        );
+        assert_eq!(
+            SsaBuilder::new(t)
+                .positions([0usize, 1])
+                .deterministic(true)
+                .end_of_text(EndOfText::Largest)
+                .build()
+                .err(),
+            Some(SsaError::DeterministicEnd)
+        );
+        assert_eq!(
+            SsaBuilder::new(t)
+                .positions([0usize, 1])
+                .deterministic(true)
+                .prefixes(true)
+                .build()
+                .err(),
+            Some(SsaError::DeterministicPrefixes)
+        );
+    }
+
+    #[test]
+    fn unsorted() {
+        let t = &(0..1000)
+            .map(|_| rand::random::<u8>() % 4)
+            .collect::<Vec<_>>();
+        let idxs = (0..t.len()).step_by(3).collect::<Vec<_>>();
+        let ssa = Ssa::new(t, &idxs);
+        let built = SsaBuilder::new(t)
+            .positions(idxs.iter().rev().copied())
+            .threads(2)
+            .build()
+            .unwrap()
+            .0;
+        assert_eq!(ssa.sa, built.sa);
+        assert_eq!(ssa.lcp, built.lcp);
+        // The empty suffix.
+        let ssa = Ssa::try_new(t, &[0, t.len()]).unwrap();
+        assert_eq!(ssa.sa, vec![t.len(), 0]);
+    }
+
+    #[test]
+    fn stats() {
+        let t = &(0..10000)
+            .map(|_| rand::random::<u8>() % 4)
+            .collect::<Vec<_>>();
+        let idxs = (0..t.len()).step_by(3).collect::<Vec<_>>();
+        let hasher = RollingHash::new(t, 64);
+        let ssa = Ssa::new(t, &idxs);
+        for threads in [1, 4] {
+            let (built, stats) = SsaBuilder::new(t)
+                .positions(idxs.iter().copied())
+                .hasher(&hasher)
+                .threads(threads)
+                .build()
+                .unwrap();
+            assert_eq!(ssa.sa, built.sa);
+            assert_eq!(ssa.lcp, built.lcp);
+            assert!(stats.hash_queries >= idxs.len());
+            // The deepest levels may sort by a single character without grouping.
+            assert!(stats.groups_per_level.len() <= stats.max_depth + 1);
+            assert!(stats.groups_per_level[0] >= 1);
+            assert_eq!(stats.hasher_time, Duration::ZERO);
+            assert!(stats.sort_time <= stats.total_time);
+        }
+
+        // Repetitive input needs the cache and deeper recursion.
+        // Sampling a third of it keeps it below the dense threshold.
+        let t = &vec![0; 1000];
+        let (_, stats) = SsaBuilder::new(t)
+            .positions((0..t.len()).step_by(3))
+            .sampling(16)
+            .build()
+            .unwrap();
+        assert!(stats.max_depth > 1);
+        assert!(stats.cache_peak > 0 || stats.groups_per_level.iter().all(|&g| g == 1));
+    }
+
+    #[test]
+    fn end_of_text() {
+        let t = b"aaaa";
+        let (ssa, _) = SsaBuilder::new(t)
+            .positions(0..t.len())
+            .end_of_text(EndOfText::Largest)
+            .build()
+            .unwrap();
+        assert_eq!(ssa.sa, vec![0, 1, 2, 3]);
+        assert_eq!(ssa.lcp, vec![3, 2, 1]);
+        assert!(!ssa.check(t));
+        assert!(ssa.verify_hashed_with(t, EndOfText::Largest, 1).is_ok());
+
+        for len in [1, 10, 100, 1000] {
+            let t = &(0..len)
+                .map(|_| rand::random::<u8>() % 2)
+                .collect::<Vec<_>>();
+            let idxs = (0..len)
+                .filter(|_| rand::random::<f64>() < 0.5)
+                .collect::<Vec<usize>>();
+            if idxs.is_empty() {
+                continue;
+            }
+            for end in [EndOfText::Smallest, EndOfText::Largest] {
+                let (ssa, _) = SsaBuilder::new(t)
+                    .positions(idxs.iter().copied())
+                    .end_of_text(end)
+                    .build()
+                    .unwrap();
+                let mut expected = idxs.clone();
+                expected.sort_by(|&a, &b| crate::cmp_suffixes(t, a, b, end));
+                assert_eq!(ssa.sa, expected);
+                ssa.verify_with(t, end);
+                assert!(ssa.verify_hashed_with(t, end, 1).is_ok());
+            }
+        }
+    }
+
+    #[test]
+    fn prefixes() {
+        for len in [1, 10, 100, 1000, 10000] {
+            let t = &(0..len)
+                .map(|_| rand::random::<u8>() % 4)
+                .collect::<Vec<_>>();
+            let rev = &t.iter().rev().copied().collect::<Vec<_>>();
+            let idxs = (0..=len)
+                .filter(|_| rand::random::<f64>() < 0.3)
+                .collect::<Vec<usize>>();
+            if idxs.is_empty() {
+                continue;
+            }
+            let hasher = RollingHash::new(t, 8);
+            for end in [EndOfText::Smallest, EndOfText::Largest] {
+                let (ssa, _) = SsaBuilder::new(t)
+                    .positions(idxs.iter().copied())
+                    .prefixes(true)
+                    .end_of_text(end)
+                    .hasher(&hasher)
+                    .threads(2)
+                    .build()
+                    .unwrap();
+                // The suffixes of the reverse text, mapped back to the end of the prefixes.
+                let (expected, _) = SsaBuilder::new(rev)
+                    .positions(idxs.iter().map(|&i| len - i))
+                    .end_of_text(end)
+                    .build()
+                    .unwrap();
+                assert!(ssa
+                    .sa
+                    .iter()
+                    .copied()
+                    .eq(expected.sa.iter().map(|&i| len - i)));
+                assert_eq!(ssa.lcp, expected.lcp);
+            }
+        }
+    }
+
+    #[test]
+    fn dense() {
+        let t = &(0..1000)
+            .map(|_| rand::random::<u8>() % 4)
+            .collect::<Vec<_>>();
+        for step in [1, 10] {
+            let idxs = (0..t.len()).step_by(step).collect::<Vec<_>>();
+            let (ssa, stats) = SsaBuilder::new(t)
+                .positions(idxs.iter().copied())
+                .build()
+                .unwrap();
+            assert_eq!(stats.dense, step == 1);
+            let expected = Ssa::new(t, &idxs);
+            assert_eq!(ssa.sa, expected.sa);
+            assert_eq!(ssa.lcp, expected.lcp);
+            let (det, stats) = SsaBuilder::new(t)
+                .positions(idxs.iter().copied())
+                .deterministic(true)
+                .build()
+                .unwrap();
+            assert!(!stats.dense);
+            assert_eq!(det.sa, expected.sa);
+            assert_eq!(det.lcp, expected.lcp);
+        }
+    }
+}
@@ -0,0 +1,138 @@
+use crate::{
+    builder::BuildStats,
+    hasher_sampling,
+    rolling_hash::RollingHash,
+    sparse_hasher_sampling,
+    verify::{self, Failure, FailureKind, Report},
+    Idx, Ssa, Suffixes,
+};
+
+impl<I: Idx> Ssa<I> {
+    /// Sort the rotations of the circular text `t` starting at `idxs`, which must be less than `t.len()`.
+    /// LCPs are at most `t.len()`. Equal rotations of a periodic text are sorted by position.
+    /// Runs on `threads` threads; `0` uses all cores.
+    pub fn new_circular(t: &[u8], idxs: &[I], threads: usize) -> Self {
+        let n = t.len();
+        assert!(
+            idxs.iter().all(|i| i.as_usize() < n),
+            "Rotations must start inside the text."
+        );
+        let hasher = RollingHash::new(t, hasher_sampling(n));
+        Self::new_with_hasher(
+            t,
+            Suffixes::Circular,
+            idxs,
+            Some(idxs.len().ilog2() as _),
+            true,
+            threads,
+            &hasher,
+            &mut BuildStats::default(),
+        )
+    }
+
+    /// Like `verify_hashed`, for an SSA built by `new_circular`.
+    pub fn verify_circular(&self, t: &[u8], threads: usize) -> Report {
+        let n = t.len();
+        let b = self.sa.len();
+        assert_eq!(self.lcp.len(), b - 1);
+        let hasher = RollingHash::new_random(t, sparse_hasher_sampling(n, b));
+
+        verify::report(b - 1, threads, |i| {
+            let (a, c, l) = (
+                self.sa[i].as_usize(),
+                self.sa[i + 1].as_usize(),
+                self.lcp[i].as_usize(),
+            );
+            let kind = if a >= n
+                || c >= n
+                || l > n
+                || hasher.query_circular(a, a..a + l) != hasher.query_circular(c, c..c + l)
+            {
+                FailureKind::LcpTooLong
+            } else if l == n {
+                // Equal rotations are sorted by position.
+                if a < c {
+                    return None;
+                }
+                FailureKind::Order
+            } else if t[(a + l) % n] < t[(c + l) % n] {
+                return None;
+            } else if t[(a + l) % n] == t[(c + l) % n] {
+                FailureKind::LcpTooShort
+            } else {
+                FailureKind::Order
+            };
+            Some(Failure {
+                i,
+                kind,
+                sa: (a, c),
+                lcp: l,
+            })
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn naive(t: &[u8], idxs: &[usize]) -> Ssa {
+        let n = t.len();
+        let rotation = |i: usize| t[i..].iter().chain(&t[..i]).copied().collect::<Vec<_>>();
+        let mut sa = idxs.to_vec();
+        sa.sort_by_key(|&i| (rotation(i), i));
+        let lcp = sa
+            .windows(2)
+            .map(|w| {
+                (0..n)
+                    .take_while(|&k| t[(w[0] + k) % n] == t[(w[1] + k) % n])
+                    .count()
+            })
+            .collect();
+        Ssa { sa, lcp }
+    }
+
+    #[test]
+    fn rotations() {
+        for &fraction in [0.1, 0.5, 1.0].iter() {
+            for len in [1, 2, 10, 100, 1000] {
+                let t = &(0..len)
+                    .map(|_| rand::random::<u8>() % 3)
+                    .collect::<Vec<_>>();
+                let idxs = (0..len)
+                    .filter(|_| rand::random::<f64>() < fraction)
+                    .collect::<Vec<usize>>();
+                if idxs.is_empty() {
+                    continue;
+                }
+                let expected = naive(t, &idxs);
+                for threads in [1, 4] {
+                    let ssa = Ssa::new_circular(t, &idxs, threads);
+                    assert_eq!(ssa.sa, expected.sa, "len {len}");
+                    assert_eq!(ssa.lcp, expected.lcp, "len {len}");
+                    assert!(ssa.verify_circular(t, threads).is_ok());
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn periodic() {
+        let t = b"abcabcabcabc";
+        let idxs = (0..t.len()).collect::<Vec<_>>();
+        let ssa = Ssa::new_circular(t, &idxs, 1);
+        assert_eq!(ssa.sa, vec![0, 3, 6, 9, 1, 4, 7, 10, 2, 5, 8, 11]);
+        assert_eq!(ssa.lcp, vec![12, 12, 12, 0, 12, 12, 12, 0, 12, 12, 12]);
+        assert!(ssa.verify_circular(t, 1).is_ok());
+
+        let mut bad = Ssa {
+            sa: ssa.sa.clone(),
+            lcp: ssa.lcp.clone(),
+        };
+        bad.sa.swap(0, 1);
+        let report = bad.verify_circular(t, 1);
+        assert_eq!(report.failures[0].kind, FailureKind::Order);
+        // The linear order differs.
+        assert!(!ssa.check(t));
+    }
+}
@@ -0,0 +1,277 @@
+//! Full suffix arrays, using SA-IS and Kasai's LCP algorithm.
+//! For high sampling densities, this is faster than sparse hash-based sorting,
+//! and it is exact, so it also serves as a reference in tests.
+use std::cmp::min;
+
+use crate::{Idx, Ssa};
+
+/// The default fraction of sampled positions above which `SsaBuilder` uses `Ssa::new_dense`, see `SsaBuilder::dense_threshold`.
+pub const DENSE_THRESHOLD: f64 = 0.5;
+
+/// Characters of a text sorted by SA-IS: bytes, or names in a reduced text.
+pub(crate) trait Char: Copy + Eq {
+    fn rank(self) -> usize;
+}
+
+impl Char for u8 {
+    fn rank(self) -> usize {
+        self as usize
+    }
+}
+
+impl<I: Idx> Char for I {
+    fn rank(self) -> usize {
+        self.as_usize()
+    }
+}
+
+/// The suffix array of `t`, in linear time.
+/// A suffix sorts before its extensions.
+pub fn sa_is<I: Idx>(t: &[u8]) -> Vec<I> {
+    sa_is_rec(t, u8::MAX as usize)
+}
+
+/// SA-IS on a text over the alphabet `0..=upper`.
+/// Besides the text, this uses about `3n` indices and `n` bytes.
+pub(crate) fn sa_is_rec<C: Char, I: Idx>(s: &[C], upper: usize) -> Vec<I> {
+    let n = s.len();
+    let none = I::MAX;
+    let (zero, one) = (I::from_usize(0), I::from_usize(1));
+    match n {
+        0 => return vec![],
+        1 => return vec![zero],
+        2 => {
+            return if s[0].rank() < s[1].rank() {
+                vec![zero, one]
+            } else {
+                vec![one, zero]
+            }
+        }
+        _ => {}
+    }
+    let c = |i: usize| s[i].rank();
+
+    // Whether each suffix is S-type, i.e. smaller than the next suffix.
+    let mut ls = vec![false; n];
+    for i in (0..n - 1).rev() {
+        ls[i] = if c(i) == c(i + 1) {
+            ls[i + 1]
+        } else {
+            c(i) < c(i + 1)
+        };
+    }
+    // Start of the L-type and S-type buckets of each character.
+    let mut sum_l = vec![0; upper + 2];
+    let mut sum_s = vec![0; upper + 2];
+    for i in 0..n {
+        if ls[i] {
+            sum_l[c(i) + 1] += 1;
+        } else {
+            sum_s[c(i)] += 1;
+        }
+    }
+    for c in 0..=upper {
+        sum_s[c] += sum_l[c];
+        sum_l[c + 1] += sum_s[c];
+    }
+
+    let mut sa = vec![none; n];
+    // Induce the order of all suffixes from the given order of LMS suffixes.
+    let induce = |sa: &mut [I], lms: &[I]| {
+        sa.fill(none);
+        let mut buf = sum_s.clone();
+        for &d in lms {
+            let d = d.as_usize();
+            sa[buf[c(d)]] = I::from_usize(d);
+            buf[c(d)] += 1;
+        }
+        buf.copy_from_slice(&sum_l);
+        sa[buf[c(n - 1)]] = I::from_usize(n - 1);
+        buf[c(n - 1)] += 1;
+        for i in 0..n {
+            let v = sa[i];
+            if v != none && v.as_usize() >= 1 && !ls[v.as_usize() - 1] {
+                let v = v.as_usize() - 1;
+                sa[buf[c(v)]] = I::from_usize(v);
+                buf[c(v)] += 1;
+            }
+        }
+        buf.copy_from_slice(&sum_l);
+        for i in (0..n).rev() {
+            let v = sa[i];
+            if v != none && v.as_usize() >= 1 && ls[v.as_usize() - 1] {
+                let v = v.as_usize() - 1;
+                buf[c(v) + 1] -= 1;
+                sa[buf[c(v) + 1]] = I::from_usize(v);
+            }
+        }
+    };
+
+    let is_lms = |i: usize| i > 0 && !ls[i - 1] && ls[i];
+    let lms = (1..n)
+        .filter(|&i| is_lms(i))
+        .map(I::from_usize)
+        .collect::<Vec<_>>();
+    let mut lms_map = vec![none; n];
+    for (k, &i) in lms.iter().enumerate() {
+        lms_map[i.as_usize()] = I::from_usize(k);
+    }
+    induce(&mut sa, &lms);
+
+    let m = lms.len();
+    if m > 0 {
+        // Name the LMS substrings in sorted order, and sort the reduced text recursively.
+        let mut sorted_lms = sa
+            .iter()
+            .copied()
+            .filter(|&v| lms_map[v.as_usize()] != none)
+            .collect::<Vec<_>>();
+        let end = |i: usize| {
+            lms.get(lms_map[i].as_usize() + 1)
+                .map_or(n, |&e| e.as_usize())
+        };
+        let mut rec_s = vec![zero; m];
+        let mut rec_upper = 0;
+        for k in 1..m {
+            let (l, r) = (sorted_lms[k - 1].as_usize(), sorted_lms[k].as_usize());
+            let (end_l, end_r) = (end(l), end(r));
+            let same =
+                end_l - l == end_r - r && end_l < n && end_r < n && s[l..=end_l] == s[r..=end_r];
+            if !same {
+                rec_upper += 1;
+            }
+            rec_s[lms_map[r].as_usize()] = I::from_usize(rec_upper);
+        }
+        drop(lms_map);
+        let rec_sa: Vec<I> = sa_is_rec(&rec_s, rec_upper);
+        for k in 0..m {
+            sorted_lms[k] = lms[rec_sa[k].as_usize()];
+        }
+        induce(&mut sa, &sorted_lms);
+    }
+    sa
+}
+
+/// The LCP array of the suffix array `sa` of `t`, where `lcp[i]` is the LCP of `sa[i]` and `sa[i+1]`.
+pub fn kasai<T: Eq, I: Idx>(t: &[T], sa: &[I]) -> Vec<I> {
+    let n = t.len();
+    let mut rank = vec![I::default(); n];
+    for (r, &i) in sa.iter().enumerate() {
+        rank[i.as_usize()] = I::from_usize(r);
+    }
+    let mut lcp = vec![I::default(); n.saturating_sub(1)];
+    let mut h: usize = 0;
+    for i in 0..n {
+        let r = rank[i].as_usize();
+        if r + 1 == n {
+            h = 0;
+            continue;
+        }
+        let j = sa[r + 1].as_usize();
+        while i + h < n && j + h < n && t[i + h] == t[j + h] {
+            h += 1;
+        }
+        lcp[r] = I::from_usize(h);
+        h = h.saturating_sub(1);
+    }
+    lcp
+}
+
+impl<I: Idx> Ssa<I> {
+    /// The full suffix array of `t`, using SA-IS and Kasai.
+    pub fn new_full(t: &[u8]) -> Self {
+        let sa = sa_is(t);
+        let lcp = kasai(t, &sa);
+        Self { sa, lcp }
+    }
+
+    /// Like `new`, but by restricting the full suffix array of `t` to `idxs`.
+    /// This is faster when most positions are sampled, but takes linear time and memory in `t.len()`:
+    /// about `3n` indices of type `I` and `2n` bytes at its peak.
+    pub fn new_dense(t: &[u8], idxs: &[I]) -> Self {
+        let n = t.len();
+        let sa: Vec<I> = sa_is(t);
+        let lcp = kasai(t, &sa);
+        let mut sampled = vec![false; n + 1];
+        for &i in idxs {
+            sampled[i.as_usize()] = true;
+        }
+
+        let mut ssa = Self {
+            sa: Vec::with_capacity(idxs.len()),
+            lcp: Vec::with_capacity(idxs.len().saturating_sub(1)),
+        };
+        // The empty suffix sorts first, and has LCP 0 with all others.
+        if sampled[n] {
+            ssa.sa.push(I::from_usize(n));
+        }
+        // The minimal LCP since the last sampled suffix.
+        let mut min_lcp = 0;
+        for (r, &i) in sa.iter().enumerate() {
+            if sampled[i.as_usize()] {
+                if !ssa.sa.is_empty() {
+                    ssa.lcp.push(I::from_usize(min_lcp));
+                }
+                ssa.sa.push(i);
+                min_lcp = usize::MAX;
+            }
+            if let Some(&l) = lcp.get(r) {
+                min_lcp = min(min_lcp, l.as_usize());
+            }
+        }
+        ssa
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn full() {
+        for len in 0..100 {
+            for sigma in [1, 2, 4, 255] {
+                let t = &(0..len)
+                    .map(|_| rand::random::<u8>() % sigma)
+                    .collect::<Vec<_>>();
+                let sa = sa_is::<usize>(t);
+                let mut expected = (0..len).collect::<Vec<_>>();
+                expected.sort_by_key(|&i| &t[i..]);
+                assert_eq!(sa, expected, "t {t:?}");
+                let lcp = kasai(t, &sa);
+                for (i, w) in sa.windows(2).enumerate() {
+                    assert_eq!(lcp[i], crate::lcp(t, w[0], w[1]));
+                }
+            }
+        }
+        let t = b"abracadabra";
+        assert_eq!(sa_is::<u32>(t), vec![10, 7, 0, 3, 5, 8, 1, 4, 6, 9, 2]);
+    }
+
+    #[test]
+    fn dense() {
+        for &fraction in [0.1, 0.5, 1.0].iter() {
+            for len in [1, 10, 100, 1000, 10000] {
+                let t = &(0..len)
+                    .map(|_| rand::random::<u8>() % 4)
+                    .collect::<Vec<_>>();
+                let idxs = (0..len)
+                    .filter(|_| rand::random::<f64>() < fraction)
+                    .collect::<Vec<usize>>();
+                if idxs.is_empty() {
+                    continue;
+                }
+                let ssa = Ssa::new(t, &idxs);
+                let dense = Ssa::new_dense(t, &idxs);
+                assert_eq!(ssa.sa, dense.sa);
+                assert_eq!(ssa.lcp, dense.lcp);
+            }
+        }
+        // Repetitive input, including the empty suffix.
+        let t = &vec![0; 1000];
+        let idxs = (0..=t.len() as u32).step_by(10).collect::<Vec<_>>();
+        let dense = Ssa::new_dense(t, &idxs);
+        assert_eq!(dense.sa, idxs.iter().rev().copied().collect::<Vec<_>>());
+        dense.verify(t);
+    }
+}
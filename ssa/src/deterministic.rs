@@ -0,0 +1,282 @@
+//! Deterministic sparse suffix sorting using a difference cover, without hashing.
+//!
+//! A difference cover `D` modulo `v` is a set of residues such that for any `i` and `j`,
+//! there is a `δ < v` for which `i+δ` and `j+δ` are both in `D` modulo `v`.
+//! The sample of suffixes starting in `D` is sorted exactly, by naming their blocks of `v` characters
+//! using prefix doubling and sorting the string of names using SA-IS.
+//! Two sparse suffixes are then compared by their first `δ` characters and the ranks of the sampled
+//! suffixes at `i+δ` and `j+δ`. Their LCP follows from an RMQ over the LCPs of the sample.
+use std::cmp::{max, min, Ordering};
+
+use rmq::{MaskRmq, Rmq};
+
+use crate::{
+    dense::{kasai, sa_is_rec},
+    lcp, Idx, Ssa,
+};
+
+const NONE: usize = usize::MAX;
+
+struct DifferenceCover {
+    v: usize,
+    /// The residues in the cover, sorted.
+    d: Vec<usize>,
+    /// The index in `d` of each residue, or `NONE`.
+    idx: Vec<usize>,
+    /// For each difference `h` modulo `v`, a residue `a` in the cover such that `a+h` is in the cover too.
+    shift: Vec<usize>,
+}
+
+impl DifferenceCover {
+    /// The cover `{0, .., s-1} ∪ {0, s, 2s, ..}` with `s = ceil(sqrt(v))`, of size about `2 sqrt(v)`.
+    /// Any difference `e` is `ks - r` for some `k` and `r < s`.
+    fn new(v: usize) -> Self {
+        let s = (1..).find(|s| s * s >= v).unwrap();
+        let mut d = (0..s)
+            .chain((0..=v.div_ceil(s)).map(|k| k * s % v))
+            .collect::<Vec<_>>();
+        d.sort_unstable();
+        d.dedup();
+        let mut idx = vec![NONE; v];
+        for (k, &r) in d.iter().enumerate() {
+            idx[r] = k;
+        }
+        // There are `O(v)` pairs of residues in the cover.
+        let mut shift = vec![NONE; v];
+        for &a in &d {
+            for &b in &d {
+                shift[(b + v - a) % v] = a;
+            }
+        }
+        Self { v, d, idx, shift }
+    }
+
+    fn contains(&self, i: usize) -> bool {
+        self.idx[i % self.v] != NONE
+    }
+
+    /// A `δ < v` such that `i+δ` and `j+δ` are both in the cover.
+    fn delta(&self, i: usize, j: usize) -> usize {
+        let (i, j) = (i % self.v, j % self.v);
+        let a = self.shift[(j + self.v - i) % self.v];
+        (a + self.v - i) % self.v
+    }
+}
+
+impl<I: Idx> Ssa<I> {
+    /// Like `new`, but deterministic: suffixes are compared exactly using a difference-cover sample
+    /// instead of hashes. The output is identical to that of `new`.
+    /// The cover is taken modulo `v ~ n/b`, so that the sample has `O(n/sqrt(v))` suffixes.
+    /// Naming the sample takes `log v` linear rounds of prefix doubling, and sorting it with SA-IS is linear.
+    /// Comparing two sparse suffixes takes `O(v)` time.
+    pub fn new_deterministic(t: &[u8], idxs: &[I]) -> Self {
+        assert!(!idxs.is_empty());
+        let n = t.len();
+        let v = (n / idxs.len()).next_power_of_two().clamp(4, 1 << 10);
+        let cover = DifferenceCover::new(v);
+
+        // 1. The reduced string contains, for each residue in the cover, the names of the blocks
+        // of the sampled positions with that residue, followed by a unique terminator.
+        let mut class_start = Vec::with_capacity(cover.d.len());
+        let mut len = 0;
+        for &r in &cover.d {
+            class_start.push(len);
+            len += (r..n).step_by(v).len() + 1;
+        }
+        let r_pos = |p: usize| class_start[cover.idx[p % v]] + p / v;
+
+        // 2. Name the blocks in sorted order. Terminators are the smallest names.
+        let (names, max_name) = block_names::<I>(t, v);
+        let mut reduced = vec![0; len];
+        for k in 0..cover.d.len() {
+            let end = class_start.get(k + 1).copied().unwrap_or(len);
+            reduced[end - 1] = k;
+        }
+        for p in (0..n).filter(|&p| cover.contains(p)) {
+            reduced[r_pos(p)] = cover.d.len() - 1 + names[p].as_usize();
+        }
+        drop(names);
+        let name = cover.d.len() - 1 + max_name;
+
+        // 3. Sort the sample exactly.
+        let sa: Vec<usize> = sa_is_rec(&reduced, name);
+        let mut rank = vec![0; len];
+        for (r, &x) in sa.iter().enumerate() {
+            rank[x] = r;
+        }
+        // LCPs in blocks. The terminators are unique, so these never cross classes.
+        let rmq = MaskRmq::new(&kasai(&reduced, &sa));
+
+        // 4. Sort and compute the LCPs of the sparse suffixes.
+        let cmp = |i: usize, j: usize| -> Ordering {
+            let delta = cover.delta(i, j);
+            let (a, b) = (&t[i..min(i + delta, n)], &t[j..min(j + delta, n)]);
+            if a != b {
+                return a.cmp(b);
+            }
+            match (i + delta == n, j + delta == n) {
+                (true, _) => Ordering::Less,
+                (_, true) => Ordering::Greater,
+                _ => rank[r_pos(i + delta)].cmp(&rank[r_pos(j + delta)]),
+            }
+        };
+        let sparse_lcp = |i: usize, j: usize| -> usize {
+            let delta = cover.delta(i, j);
+            let l = std::iter::zip(&t[i..], &t[j..])
+                .take(delta)
+                .take_while(|(a, b)| a == b)
+                .count();
+            if l < delta || i + delta == n || j + delta == n {
+                return l;
+            }
+            let (p, q) = (i + delta, j + delta);
+            let (rp, rq) = (rank[r_pos(p)], rank[r_pos(q)]);
+            let blocks = rmq.query(min(rp, rq)..max(rp, rq));
+            // The next blocks differ, so this scans at most `v` characters.
+            delta + blocks * v + lcp(t, p + blocks * v, q + blocks * v)
+        };
+
+        let mut sa = idxs.iter().map(|i| i.as_usize()).collect::<Vec<_>>();
+        sa.sort_by(|&i, &j| cmp(i, j));
+        Self {
+            lcp: sa
+                .windows(2)
+                .map(|w| I::from_usize(sparse_lcp(w[0], w[1])))
+                .collect(),
+            sa: sa.into_iter().map(I::from_usize).collect(),
+        }
+    }
+}
+
+/// For each position, the rank `1..` of the block `t[p..p+v]` among all distinct blocks, where a block
+/// sorts before its extensions, and the largest rank. `v` must be a power of two, at least 2.
+/// Uses prefix doubling, where each round sorts pairs of names with two counting sorts.
+fn block_names<I: Idx>(t: &[u8], v: usize) -> (Vec<I>, usize) {
+    let n = t.len();
+    // 0 is the name of the empty block past the end of the text.
+    let mut names = t
+        .iter()
+        .map(|&c| I::from_usize(c as usize + 1))
+        .collect::<Vec<_>>();
+    let mut max_name = u8::MAX as usize + 1;
+    let mut order = vec![I::default(); n];
+    let mut tmp = vec![I::default(); n];
+    let mut l = 1;
+    let mut distinct = false;
+    while l < v && !distinct {
+        let second = |p: usize| names.get(p + l).map_or(0, |x| x.as_usize());
+        let first = |p: usize| names[p].as_usize();
+        // Sort by the second name, then stably by the first.
+        let mut counts = vec![0; max_name + 2];
+        counting_sort((0..n).map(I::from_usize), second, &mut counts, &mut tmp);
+        counts.fill(0);
+        counting_sort(tmp.iter().copied(), first, &mut counts, &mut order);
+        let mut new_names = tmp;
+        let mut name = 0;
+        for k in 0..n {
+            let p = order[k].as_usize();
+            let q = order[k.saturating_sub(1)].as_usize();
+            if k == 0 || (first(q), second(q)) != (first(p), second(p)) {
+                name += 1;
+            }
+            new_names[p] = I::from_usize(name);
+        }
+        tmp = std::mem::replace(&mut names, new_names);
+        max_name = name;
+        distinct = name == n;
+        l *= 2;
+    }
+    // Once all names are distinct, longer blocks have the same ranks.
+    (names, max_name)
+}
+
+/// Stably sort `items` into `out` by `key`, using `counts` of length at least the largest key plus 2.
+fn counting_sort<I: Idx>(
+    items: impl Iterator<Item = I> + Clone,
+    key: impl Fn(usize) -> usize,
+    counts: &mut [usize],
+    out: &mut [I],
+) {
+    for x in items.clone() {
+        counts[key(x.as_usize()) + 1] += 1;
+    }
+    for k in 1..counts.len() {
+        counts[k] += counts[k - 1];
+    }
+    for x in items {
+        let k = key(x.as_usize());
+        out[counts[k]] = x;
+        counts[k] += 1;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn cover() {
+        for v in [4, 8, 64, 100, 1 << 10] {
+            let cover = DifferenceCover::new(v);
+            assert!(cover.d.len() <= 2 * (1..).find(|s| s * s >= v).unwrap() + 1);
+            for i in 0..v {
+                for j in 0..v {
+                    let delta = cover.delta(i, j);
+                    assert!(delta < v);
+                    assert!(cover.contains(i + delta) && cover.contains(j + delta));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn block_names() {
+        for len in [0, 1, 10, 100, 1000] {
+            for sigma in [1, 2, 4] {
+                let t = &(0..len)
+                    .map(|_| rand::random::<u8>() % sigma)
+                    .collect::<Vec<_>>();
+                for v in [2, 4, 16, 64] {
+                    let (names, max_name) = super::block_names::<u32>(t, v);
+                    let block = |p: usize| &t[p..min(p + v, len)];
+                    let mut blocks = (0..len).map(block).collect::<Vec<_>>();
+                    blocks.sort();
+                    blocks.dedup();
+                    for (p, &name) in names.iter().enumerate() {
+                        let rank = blocks.binary_search(&block(p)).unwrap() + 1;
+                        assert_eq!(name as usize, rank, "len {len} v {v} p {p}");
+                    }
+                    assert_eq!(max_name, blocks.len());
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn deterministic() {
+        for &fraction in [0.01, 0.1, 0.5, 1.0].iter() {
+            for len in [1, 10, 100, 1000, 10000] {
+                let t = &(0..len)
+                    .map(|_| rand::random::<u8>() % 4)
+                    .collect::<Vec<_>>();
+                let idxs = (0..len)
+                    .filter(|_| rand::random::<f64>() < fraction)
+                    .collect::<Vec<usize>>();
+                if idxs.is_empty() {
+                    continue;
+                }
+                let ssa = Ssa::new_dense(t, &idxs);
+                let det = Ssa::new_deterministic(t, &idxs);
+                assert_eq!(ssa.sa, det.sa, "len {len}");
+                assert_eq!(ssa.lcp, det.lcp, "len {len}");
+            }
+        }
+        // Repetitive input, including the empty suffix.
+        let t = &b"ab".repeat(5000);
+        let idxs = (0..=t.len() as u32).step_by(7).collect::<Vec<_>>();
+        let ssa = Ssa::new(t, &idxs);
+        let det = Ssa::new_deterministic(t, &idxs);
+        assert_eq!(ssa.sa, det.sa);
+        assert_eq!(ssa.lcp, det.lcp);
+    }
+}
@@ -0,0 +1,325 @@
+//! Binary file format for `Ssa`.
+//!
+//! A file consists of a header followed by the SA and the LCP array.
+//! The header is the magic string followed by little-endian `u64` words:
+//! version, index width in bytes, text length, text checksum,
+//! sampling scheme, `k`, `w`, `l0` (`u64::MAX` for `None`), and the number of sampled suffixes.
+//! The arrays are stored as raw little-endian integers of the index width,
+//! so that they can be memory-mapped directly.
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    marker::PhantomData,
+    mem::size_of,
+    ops::Range,
+    path::Path,
+};
+
+use memmap2::Mmap;
+
+use crate::{rolling_hash::RollingHash, search, Idx, Ssa};
+
+// Mapped arrays are read as native integers.
+#[cfg(not(target_endian = "little"))]
+compile_error!("SSA files can only be mapped on little endian machines.");
+
+const MAGIC: [u8; 8] = *b"SSAINDEX";
+/// Increment on every incompatible change of the format.
+const VERSION: u64 = 1;
+const HEADER_WORDS: usize = 9;
+/// A multiple of 8, so that the arrays following it are aligned.
+const HEADER_LEN: usize = MAGIC.len() + 8 * HEADER_WORDS;
+
+/// How the sampled suffixes were chosen.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Sampling {
+    /// An arbitrary given set of positions.
+    Explicit,
+    /// Minimizers of `k`-mers in windows of `w` `k`-mers.
+    Minimizers { k: usize, w: usize },
+    /// Minimizers, sorted as prefixes in the reverse text, as done by `lcr::build_ssas`.
+    ReverseMinimizers { k: usize, w: usize },
+}
+
+/// Metadata stored alongside the SA and LCP arrays.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Meta {
+    /// Length of the text.
+    pub n: usize,
+    /// Checksum of the text, see `checksum`.
+    pub checksum: u64,
+    pub sampling: Sampling,
+    /// The `l0` used for construction.
+    pub l0: Option<usize>,
+}
+
+impl Meta {
+    pub fn new(t: &[u8], sampling: Sampling, l0: Option<usize>) -> Self {
+        Self {
+            n: t.len(),
+            checksum: checksum(t),
+            sampling,
+            l0,
+        }
+    }
+    /// Whether the metadata was created for text `t`.
+    pub fn matches(&self, t: &[u8]) -> bool {
+        self.n == t.len() && self.checksum == checksum(t)
+    }
+}
+
+/// Checksum of a text, to detect SSAs built on a different text.
+pub fn checksum(t: &[u8]) -> u64 {
+    RollingHash::linear(t).0
+}
+
+/// The header of an SSA file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Header {
+    pub meta: Meta,
+    /// Size of the index type in bytes.
+    pub width: usize,
+    /// The number of sampled suffixes.
+    pub len: usize,
+}
+
+fn invalid(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+impl Header {
+    /// Read only the header of an SSA file.
+    pub fn read(path: &Path) -> io::Result<Self> {
+        let mut bytes = [0; HEADER_LEN];
+        io::Read::read_exact(&mut File::open(path)?, &mut bytes)?;
+        Self::decode(&bytes)
+    }
+
+    pub(crate) fn encode(&self) -> [u8; HEADER_LEN] {
+        let (scheme, k, w) = match self.meta.sampling {
+            Sampling::Explicit => (0, 0, 0),
+            Sampling::Minimizers { k, w } => (1, k, w),
+            Sampling::ReverseMinimizers { k, w } => (2, k, w),
+        };
+        let words: [u64; HEADER_WORDS] = [
+            VERSION,
+            self.width as u64,
+            self.meta.n as u64,
+            self.meta.checksum,
+            scheme,
+            k as u64,
+            w as u64,
+            self.meta.l0.map_or(u64::MAX, |l0| l0 as u64),
+            self.len as u64,
+        ];
+        let mut bytes = [0; HEADER_LEN];
+        bytes[..MAGIC.len()].copy_from_slice(&MAGIC);
+        for (chunk, word) in bytes[MAGIC.len()..].chunks_exact_mut(8).zip(words) {
+            chunk.copy_from_slice(&word.to_le_bytes());
+        }
+        bytes
+    }
+
+    fn decode(bytes: &[u8]) -> io::Result<Self> {
+        if bytes.len() < HEADER_LEN || bytes[..MAGIC.len()] != MAGIC {
+            return Err(invalid("Not an SSA file.".into()));
+        }
+        let word = |i: usize| {
+            let start = MAGIC.len() + 8 * i;
+            u64::from_le_bytes(bytes[start..start + 8].try_into().unwrap())
+        };
+        if word(0) != VERSION {
+            return Err(invalid(format!(
+                "Unsupported SSA file version {}, expected {VERSION}.",
+                word(0)
+            )));
+        }
+        let (k, w) = (word(5) as usize, word(6) as usize);
+        let sampling = match word(4) {
+            0 => Sampling::Explicit,
+            1 => Sampling::Minimizers { k, w },
+            2 => Sampling::ReverseMinimizers { k, w },
+            s => return Err(invalid(format!("Unknown sampling scheme {s}."))),
+        };
+        Ok(Self {
+            meta: Meta {
+                n: word(2) as usize,
+                checksum: word(3),
+                sampling,
+                l0: (word(7) != u64::MAX).then_some(word(7) as usize),
+            },
+            width: word(1) as usize,
+            len: word(8) as usize,
+        })
+    }
+
+    /// The total size in bytes of the file.
+    fn file_len(&self) -> Option<usize> {
+        let words = self.len.checked_mul(2)?.checked_sub(1)?;
+        words.checked_mul(self.width)?.checked_add(HEADER_LEN)
+    }
+}
+
+/// View the array as raw bytes.
+pub(crate) fn as_bytes<I: Idx>(v: &[I]) -> &[u8] {
+    // SAFETY: `Idx` is sealed and only implemented for `u32` and `usize`, which have no padding.
+    unsafe { std::slice::from_raw_parts(v.as_ptr() as *const u8, std::mem::size_of_val(v)) }
+}
+
+impl<I: Idx> Ssa<I> {
+    /// Write the SSA and its metadata to `path`.
+    pub fn save(&self, path: &Path, meta: &Meta) -> io::Result<()> {
+        let header = Header {
+            meta: *meta,
+            width: size_of::<I>(),
+            len: self.sa.len(),
+        };
+        let mut out = BufWriter::new(File::create(path)?);
+        out.write_all(&header.encode())?;
+        out.write_all(as_bytes(&self.sa))?;
+        out.write_all(as_bytes(&self.lcp))?;
+        out.flush()
+    }
+}
+
+/// An SSA file memory-mapped by `open`. The arrays are not copied.
+pub struct MappedSsa<I = usize> {
+    pub header: Header,
+    mmap: Mmap,
+    _idx: PhantomData<I>,
+}
+
+impl<I: Idx> MappedSsa<I> {
+    /// Map the SSA file at `path`.
+    /// The file must not be modified while it is mapped.
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let file = File::open(path)?;
+        // SAFETY: We assume the file is not modified while it is mapped.
+        let mmap = unsafe { Mmap::map(&file)? };
+        let header = Header::decode(&mmap)?;
+        if header.width != size_of::<I>() {
+            return Err(invalid(format!(
+                "SSA file has {}-byte indices, but {}-byte indices were requested.",
+                header.width,
+                size_of::<I>()
+            )));
+        }
+        if header.file_len() != Some(mmap.len()) {
+            return Err(invalid(format!(
+                "SSA file has length {}, but the header implies {:?}.",
+                mmap.len(),
+                header.file_len()
+            )));
+        }
+        Ok(Self {
+            header,
+            mmap,
+            _idx: PhantomData,
+        })
+    }
+
+    fn slice(&self, offset: usize, len: usize) -> &[I] {
+        // SAFETY: `open` checked the length of the file. The mapping is page aligned and
+        // `offset` is a multiple of the width, so the slice is aligned.
+        // `Idx` is sealed and only implemented for `u32` and `usize`, for which any bit pattern is valid.
+        unsafe { std::slice::from_raw_parts(self.mmap[offset..].as_ptr() as *const I, len) }
+    }
+    pub fn sa(&self) -> &[I] {
+        self.slice(HEADER_LEN, self.header.len)
+    }
+    pub fn lcp(&self) -> &[I] {
+        let offset = HEADER_LEN + self.header.len * self.header.width;
+        self.slice(offset, self.header.len - 1)
+    }
+
+    /// Copy the arrays into an owned `Ssa`, e.g. for algorithms that modify them.
+    /// Prefer `sa` and `lcp` for read-only use, which do not copy.
+    pub fn to_ssa(&self) -> Ssa<I> {
+        Ssa {
+            sa: self.sa().to_vec(),
+            lcp: self.lcp().to_vec(),
+        }
+    }
+
+    /// See `Ssa::find`.
+    pub fn find(&self, text: &RollingHash, pattern: &[u8]) -> Range<usize> {
+        search::find(self.sa(), text, pattern)
+    }
+    /// See `Ssa::count`.
+    pub fn count(&self, text: &RollingHash, pattern: &[u8]) -> usize {
+        self.find(text, pattern).len()
+    }
+    /// See `Ssa::locate`.
+    pub fn locate(&self, text: &RollingHash, pattern: &[u8]) -> &[I] {
+        &self.sa()[self.find(text, pattern)]
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    fn tmp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("ssa-{}-{name}", std::process::id()))
+    }
+
+    fn roundtrip<I: Idx>(name: &str) {
+        let len = 1000;
+        let t = &(0..len)
+            .map(|_| rand::random::<u8>() % 4)
+            .collect::<Vec<_>>();
+        let idxs = (0..len)
+            .filter(|_| rand::random::<f64>() < 0.5)
+            .map(I::from_usize)
+            .collect::<Vec<_>>();
+        let ssa = Ssa::new(t, &idxs);
+        let meta = Meta::new(t, Sampling::Minimizers { k: 4, w: 5 }, Some(8));
+
+        let path = tmp_path(name);
+        ssa.save(&path, &meta).unwrap();
+        let header = Header::read(&path).unwrap();
+        assert_eq!(header.meta, meta);
+        assert!(header.meta.matches(t));
+        assert_eq!(header.width, size_of::<I>());
+        assert_eq!(header.len, idxs.len());
+
+        let mapped = MappedSsa::<I>::open(&path).unwrap();
+        assert_eq!(mapped.sa(), &ssa.sa[..]);
+        assert_eq!(mapped.lcp(), &ssa.lcp[..]);
+        let hasher = RollingHash::new(t, 8);
+        assert_eq!(
+            mapped.find(&hasher, &t[10..14]),
+            ssa.find(&hasher, &t[10..14])
+        );
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn save_and_open() {
+        roundtrip::<u32>("u32");
+        roundtrip::<usize>("usize");
+    }
+
+    #[test]
+    fn bad_files() {
+        let t = b"abracadabra";
+        let idxs = (0..t.len() as u32).collect::<Vec<_>>();
+        let ssa = Ssa::new(t, &idxs);
+        let path = tmp_path("bad");
+        ssa.save(&path, &Meta::new(t, Sampling::Explicit, None))
+            .unwrap();
+        assert!(!Header::read(&path).unwrap().meta.matches(b"abracadabrx"));
+        // Wrong index width.
+        assert!(MappedSsa::<usize>::open(&path).is_err());
+        // Truncated.
+        let bytes = std::fs::read(&path).unwrap();
+        std::fs::write(&path, &bytes[..bytes.len() - 1]).unwrap();
+        assert!(MappedSsa::<u32>::open(&path).is_err());
+        // Not an SSA file.
+        std::fs::write(&path, t).unwrap();
+        assert!(MappedSsa::<u32>::open(&path).is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,212 @@
+use std::cmp::Ordering;
+
+use crate::{builder::BuildStats, hasher_sampling, rolling_hash::RollingHash, Idx, Ssa, Suffixes};
+
+/// A collection of texts, stored consecutively.
+#[derive(Debug, Default, Clone)]
+pub struct Records {
+    /// The concatenation of all records.
+    pub text: Vec<u8>,
+    /// Exclusive end of each record in `text`.
+    pub ends: Vec<usize>,
+}
+
+impl Records {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn push(&mut self, record: &[u8]) {
+        self.text.extend_from_slice(record);
+        self.ends.push(self.text.len());
+    }
+    /// The number of records.
+    pub fn len(&self) -> usize {
+        self.ends.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.ends.is_empty()
+    }
+    /// Start of record `r` in `text`.
+    pub fn start(&self, r: usize) -> usize {
+        if r == 0 {
+            0
+        } else {
+            self.ends[r - 1]
+        }
+    }
+    pub fn get(&self, r: usize) -> &[u8] {
+        &self.text[self.start(r)..self.ends[r]]
+    }
+    /// Map a position in `text` to a `(record, offset)` pair.
+    pub fn locate(&self, pos: usize) -> (usize, usize) {
+        let r = self.ends.partition_point(|&end| end <= pos);
+        (r, pos - self.start(r))
+    }
+}
+
+impl<'a> FromIterator<&'a [u8]> for Records {
+    fn from_iter<T: IntoIterator<Item = &'a [u8]>>(iter: T) -> Self {
+        let mut records = Self::new();
+        for record in iter {
+            records.push(record);
+        }
+        records
+    }
+}
+
+/// A generalized sparse suffix array over a collection of records.
+/// Each suffix ends at the end of its record, so LCPs never cross record boundaries.
+/// Equal suffixes of different records are ordered by record.
+pub struct GSsa<I = usize> {
+    /// Sampled suffixes as `(record, offset)` pairs.
+    pub sa: Vec<(I, I)>,
+    pub lcp: Vec<I>,
+}
+
+impl<I: Idx> GSsa<I> {
+    /// Build the generalized SSA of the given `(record, offset)` pairs.
+    pub fn new(records: &Records, idxs: &[(I, I)]) -> Self {
+        Self::new_par(records, idxs, 1)
+    }
+    /// Like `new`, but sort independent groups on `threads` threads.
+    /// `threads == 0` uses all available cores.
+    pub fn new_par(records: &Records, idxs: &[(I, I)], threads: usize) -> Self {
+        let t = &records.text;
+        let idxs = idxs
+            .iter()
+            .map(|&(r, o)| {
+                let (r, o) = (r.as_usize(), o.as_usize());
+                assert!(o < records.get(r).len(), "Offset {o} not in record {r}.");
+                I::from_usize(records.start(r) + o)
+            })
+            .collect::<Vec<_>>();
+        let hasher = RollingHash::new(t, hasher_sampling(t.len()));
+        let l0 = Some(idxs.len().ilog2() as _);
+        let ssa = Ssa::new_with_hasher(
+            t,
+            Suffixes::Records(&records.ends),
+            &idxs,
+            l0,
+            true,
+            threads,
+            &hasher,
+            &mut BuildStats::default(),
+        );
+        Self {
+            sa: ssa
+                .sa
+                .iter()
+                .map(|&i| {
+                    let (r, o) = records.locate(i.as_usize());
+                    (I::from_usize(r), I::from_usize(o))
+                })
+                .collect(),
+            lcp: ssa.lcp,
+        }
+    }
+
+    fn suffix<'t>(&self, records: &'t Records, i: usize) -> &'t [u8] {
+        let (r, o) = self.sa[i];
+        &records.get(r.as_usize())[o.as_usize()..]
+    }
+
+    /// Naive check of the SA and LCP arrays.
+    pub fn verify(&self, records: &Records) {
+        let b = self.sa.len();
+        assert_eq!(self.lcp.len(), b - 1);
+        for i in 0..b - 1 {
+            let (s0, s1) = (self.suffix(records, i), self.suffix(records, i + 1));
+            let order = s0.cmp(s1).then(self.sa[i].0.cmp(&self.sa[i + 1].0));
+            assert_eq!(
+                order,
+                Ordering::Less,
+                "Bad order at position {i}: {:?} and {:?}",
+                self.sa[i],
+                self.sa[i + 1]
+            );
+            let l = std::iter::zip(s0, s1).take_while(|(a, b)| a == b).count();
+            assert_eq!(
+                l,
+                self.lcp[i].as_usize(),
+                "Bad LCP at position {i}. Got {:?}",
+                self.lcp[i]
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn random_records(lens: &[usize]) -> Records {
+        lens.iter()
+            .map(|&len| {
+                (0..len)
+                    .map(|_| rand::random::<u8>() % 4)
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>()
+            .iter()
+            .map(|r| &r[..])
+            .collect()
+    }
+
+    fn all_idxs(records: &Records) -> Vec<(usize, usize)> {
+        (0..records.len())
+            .flat_map(|r| (0..records.get(r).len()).map(move |o| (r, o)))
+            .collect()
+    }
+
+    #[test]
+    fn equal_records() {
+        let records = [&b"abab"[..], b"abab", b"ab", b"b"]
+            .into_iter()
+            .collect::<Records>();
+        let gssa = GSsa::new(&records, &all_idxs(&records));
+        gssa.verify(&records);
+        assert_eq!(
+            gssa.sa,
+            vec![
+                (0, 2),
+                (1, 2),
+                (2, 0),
+                (0, 0),
+                (1, 0),
+                (0, 3),
+                (1, 3),
+                (2, 1),
+                (3, 0),
+                (0, 1),
+                (1, 1),
+            ]
+        );
+        assert_eq!(gssa.lcp, vec![2, 2, 2, 4, 0, 1, 1, 1, 1, 3]);
+    }
+
+    #[test]
+    fn random_gssa() {
+        for &fraction in [0.1, 0.5, 1.0].iter() {
+            for lens in [
+                vec![1, 1, 1],
+                vec![10, 0, 10],
+                vec![100; 10],
+                vec![1000, 10, 1000],
+            ] {
+                let records = random_records(&lens);
+                let idxs = all_idxs(&records)
+                    .into_iter()
+                    .filter(|_| rand::random::<f64>() < fraction)
+                    .collect::<Vec<_>>();
+                if idxs.is_empty() {
+                    continue;
+                }
+                let gssa = GSsa::new(&records, &idxs);
+                gssa.verify(&records);
+                let gssa_par = GSsa::new_par(&records, &idxs, 4);
+                assert_eq!(gssa.sa, gssa_par.sa);
+                assert_eq!(gssa.lcp, gssa_par.lcp);
+            }
+        }
+    }
+}
@@ -0,0 +1,92 @@
+use std::{
+    cmp::{max, min},
+    collections::HashMap,
+};
+
+use rmq::{MaskRmq, Rmq};
+
+use crate::{Idx, Ssa};
+
+/// An `Ssa` with a sparse inverse SA and an RMQ over the LCP array,
+/// to answer LCP queries between any two sampled suffixes in O(1).
+pub struct SsaIndex<I = usize> {
+    pub ssa: Ssa<I>,
+    /// Length of the text.
+    n: usize,
+    /// The rank in the SA of each sampled position.
+    isa: HashMap<I, I>,
+    /// `None` when there is only a single suffix.
+    rmq: Option<MaskRmq<I>>,
+}
+
+impl<I: Idx> SsaIndex<I> {
+    /// `n` is the length of the text the SSA was built on.
+    pub fn new(ssa: Ssa<I>, n: usize) -> Self {
+        let isa = ssa
+            .sa
+            .iter()
+            .enumerate()
+            .map(|(rank, &i)| (i, I::from_usize(rank)))
+            .collect();
+        let rmq = (!ssa.lcp.is_empty()).then(|| MaskRmq::new(&ssa.lcp));
+        Self { ssa, n, isa, rmq }
+    }
+
+    /// The rank of the suffix starting at `i`, or `None` if `i` is not sampled.
+    pub fn rank(&self, i: usize) -> Option<usize> {
+        // Larger positions are not sampled, and may not fit in `I`.
+        if i > self.n {
+            return None;
+        }
+        self.isa.get(&I::from_usize(i)).map(|r| r.as_usize())
+    }
+
+    /// The LCP of the suffixes starting at `i` and `j`, or `None` if either is not sampled.
+    pub fn lcp(&self, i: usize, j: usize) -> Option<usize> {
+        let (ri, rj) = (self.rank(i)?, self.rank(j)?);
+        if ri == rj {
+            return Some(self.n - i);
+        }
+        let rmq = self.rmq.as_ref().unwrap();
+        Some(rmq.query(min(ri, rj)..max(ri, rj)).as_usize())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn lcp() {
+        for len in [1, 10, 100, 1000] {
+            let t = &(0..len)
+                .map(|_| rand::random::<u8>() % 4)
+                .collect::<Vec<_>>();
+            let idxs = (0..len)
+                .filter(|_| rand::random::<f64>() < 0.5)
+                .collect::<Vec<usize>>();
+            if idxs.is_empty() {
+                continue;
+            }
+            let index = SsaIndex::new(Ssa::new(t, &idxs), len);
+            for _ in 0..1000 {
+                let i = rand::random::<usize>() % len;
+                let j = rand::random::<usize>() % len;
+                let expected =
+                    (idxs.contains(&i) && idxs.contains(&j)).then(|| crate::lcp(t, i, j));
+                assert_eq!(index.lcp(i, j), expected, "i {i} j {j}");
+            }
+        }
+    }
+
+    #[test]
+    fn out_of_range() {
+        let t = b"abracadabra";
+        let idxs = [0u32, 3, 7];
+        let index = SsaIndex::new(Ssa::new(t, &idxs), t.len());
+        assert_eq!(index.rank(3), Some(2));
+        assert_eq!(index.rank(t.len()), None);
+        assert_eq!(index.rank(u32::MAX as usize + 1), None);
+        assert_eq!(index.lcp(0, usize::MAX), None);
+    }
+}
@@ -3,63 +3,168 @@ use std::cmp::min;
 use coloured_trees::Tree;
 
 use crate::{
+    builder::{BuildStats, SsaBuilder},
+    hasher_sampling, index_fits,
     minimizers::{self},
-    Ssa,
+    rolling_hash::RollingHash,
+    Idx, Ssa, Suffixes,
 };
 
 /// Find the longest common repeat length within a string.
-pub fn lcr(t: &mut [u8], l: usize) -> usize {
+/// The suffix arrays are built on `threads` threads; `0` uses all cores.
+/// Uses compact `u32` suffix arrays when the text is small enough.
+pub fn lcr(t: &[u8], l: usize, threads: usize) -> usize {
+    let minimizers = find_minimizers(t, l);
+    if index_fits::<u32>(t.len(), minimizers.len()) {
+        lcr_on_minimizers::<u32>(t, minimizers, threads)
+    } else {
+        lcr_on_minimizers::<usize>(t, minimizers, threads)
+    }
+}
+
+/// `lcr`, using suffix arrays with index type `I`.
+pub fn lcr_with<I: Idx>(t: &[u8], l: usize, threads: usize) -> usize {
+    let minimizers = find_minimizers(t, l);
+    lcr_on_minimizers::<I>(t, minimizers, threads)
+}
+
+/// Like `lcr`, for a circular text, so that repeats spanning the end of the text are found.
+/// Repeats are at most `t.len()` long.
+pub fn lcr_circular(t: &[u8], l: usize, threads: usize) -> usize {
+    let minimizers = find_minimizers_circular(t, l);
+    if index_fits::<u32>(t.len(), minimizers.len()) {
+        lcr_circular_on_minimizers::<u32>(t, minimizers, threads)
+    } else {
+        lcr_circular_on_minimizers::<usize>(t, minimizers, threads)
+    }
+}
+
+/// The minimizer `k` and `w` used to find repeats of length at least `l`.
+pub fn minimizer_params(l: usize) -> (usize, usize) {
     let k = min(l / 2, 64);
     let w = l - k - 1;
+    (k, w)
+}
+
+pub fn find_minimizers(t: &[u8], l: usize) -> Vec<usize> {
+    let (k, w) = minimizer_params(l);
     // 1. Find minimizers
-    let start = std::time::Instant::now();
     eprintln!("Find minimizers");
 
     let start = std::time::Instant::now();
-    let mut minimizers = minimizers::minimizers_daniel(t, k, w);
+    let minimizers = minimizers::minimizers_daniel(t, k, w);
     eprintln!("\tTime: {:?}", start.elapsed());
     eprintln!("Minimizers: {}", minimizers.len());
     eprintln!("1/density : {}", t.len() as f32 / minimizers.len() as f32);
+    minimizers
+}
+
+/// Like `find_minimizers`, including the windows that wrap around the end of the text.
+pub fn find_minimizers_circular(t: &[u8], l: usize) -> Vec<usize> {
+    let (k, w) = minimizer_params(l);
+    let n = t.len();
+    let extended = t
+        .iter()
+        .cycle()
+        .take(n + k + w - 2)
+        .copied()
+        .collect::<Vec<_>>();
+    let mut minimizers = minimizers::minimizers_daniel(&extended, k, w)
+        .into_iter()
+        .map(|i| i % n)
+        .collect::<Vec<_>>();
+    minimizers.sort_unstable();
+    minimizers.dedup();
+    eprintln!("Minimizers: {}", minimizers.len());
+    minimizers
+}
+
+fn lcr_circular_on_minimizers<I: Idx>(t: &[u8], minimizers: Vec<usize>, threads: usize) -> usize {
+    let (ssa, ssa_rev) = build_circular_ssas::<I>(t, minimizers, threads);
+    min(lcr_from_ssas(ssa, ssa_rev), t.len())
+}
+
+/// Like `build_ssas`, sorting rotations instead of suffixes.
+/// Position `i` in the reverse SSA represents the rotation of the reverse text ending just before `i`.
+pub fn build_circular_ssas<I: Idx>(
+    t: &[u8],
+    minimizers: Vec<usize>,
+    threads: usize,
+) -> (Ssa<I>, Ssa<I>) {
+    let minimizers: Vec<I> = minimizers.into_iter().map(I::from_usize).collect();
+    assert!(
+        minimizers.iter().all(|i| i.as_usize() < t.len()),
+        "Rotations must start inside the text."
+    );
+    let hasher = RollingHash::new(t, hasher_sampling(t.len()));
+    let build = |suffixes| {
+        Ssa::new_with_hasher(
+            t,
+            suffixes,
+            &minimizers,
+            Some(minimizers.len().ilog2() as _),
+            true,
+            threads,
+            &hasher,
+            &mut BuildStats::default(),
+        )
+    };
+    (build(Suffixes::Circular), build(Suffixes::CircularPrefixes))
+}
+
+fn lcr_on_minimizers<I: Idx>(t: &[u8], minimizers: Vec<usize>, threads: usize) -> usize {
+    let (ssa, ssa_rev) = build_ssas::<I>(t, minimizers, threads);
+    lcr_from_ssas(ssa, ssa_rev)
+}
+
+/// Build the SSA of the minimizers, and the sparse prefix array of the reverse prefixes ending at the minimizers.
+/// Both share a single hasher.
+pub fn build_ssas<I: Idx>(t: &[u8], minimizers: Vec<usize>, threads: usize) -> (Ssa<I>, Ssa<I>) {
+    let minimizers: Vec<I> = minimizers.into_iter().map(I::from_usize).collect();
+    let hasher = RollingHash::new(t, hasher_sampling(t.len()));
+    let build = |prefixes| {
+        SsaBuilder::new(t)
+            .positions(minimizers.iter().copied())
+            .threads(threads)
+            .hasher(&hasher)
+            .prefixes(prefixes)
+            .build()
+            .unwrap()
+            .0
+    };
 
     // eprintln!("Minimizers: {:?}", minimizers);
 
     // 2. SSA on minimizers.
     eprintln!("SSA1");
     let start = std::time::Instant::now();
-    let ssa = Ssa::new(t, &minimizers);
+    let ssa = build(false);
     eprintln!("\tTime: {:?}", start.elapsed());
     // ssa.verify(t);
     // ssa.print(t);
 
-    // 3. SSA on revere string minimizers.
+    // 3. Sparse prefix array on minimizers.
     eprintln!("SSA2");
     let start = std::time::Instant::now();
-    for i in &mut minimizers {
-        *i = t.len() - *i;
-    }
-    t.reverse();
-    let mut ssa_rev = Ssa::new(t, &minimizers);
-    // ssa_rev.verify(t);
-    // ssa_rev.print(t);
-    t.reverse();
-    for i in &mut ssa_rev.sa {
-        *i = t.len() - *i;
-    }
+    let ssa_rev = build(true);
     eprintln!("\tTime: {:?}", start.elapsed());
+    (ssa, ssa_rev)
+}
 
-    // Solve the common-tree problem.
+/// Solve the common-tree problem on the SSAs returned by `build_ssas`.
+pub fn lcr_from_ssas<I: Idx>(ssa: Ssa<I>, ssa_rev: Ssa<I>) -> usize {
     eprintln!("LCR");
     let start = std::time::Instant::now();
     let mut t1 = Tree {
         sa: ssa.sa,
         lcp: ssa.lcp,
     };
-    t1.lcp.push(0);
+    t1.lcp.push(I::default());
     let mut t2 = Tree {
         sa: ssa_rev.sa,
         lcp: ssa_rev.lcp,
     };
-    t2.lcp.push(0);
+    t2.lcp.push(I::default());
 
     // eprintln!("t1: {:?}", t1);
     // eprintln!("t2: {:?}", t2);
@@ -75,26 +180,35 @@ pub fn lcr(t: &mut [u8], l: usize) -> usize {
 mod test {
     use rand::{distributions::Alphanumeric, random, thread_rng, Rng};
 
+    use super::*;
+
+    fn gen(len: usize) -> Vec<u8> {
+        thread_rng()
+            .sample_iter(&Alphanumeric)
+            .take(len)
+            .collect::<Vec<_>>()
+    }
+
     #[test]
     fn small() {
-        let mut t = b"ABRACADABRAXYZPT".to_vec();
-        let lcr = super::lcr(&mut t, 4);
+        let t = b"ABRACADABRAXYZPT".to_vec();
+        let lcr = super::lcr(&t, 4, 1);
         assert_eq!(lcr, 4);
     }
     #[test]
+    fn compact() {
+        let t = b"ABRACADABRAXYZPT".to_vec();
+        assert_eq!(super::lcr_with::<u32>(&t, 4, 1), 4);
+        assert_eq!(super::lcr_with::<usize>(&t, 4, 1), 4);
+    }
+    #[test]
     fn large() {
         // Generate three random strings.
-        let gen = |len: usize| {
-            thread_rng()
-                .sample_iter(&Alphanumeric)
-                .take(len)
-                .collect::<Vec<_>>()
-        };
         let t1 = gen(random::<usize>() % 10);
         let t2 = gen(random::<usize>() % 10);
         let t3 = gen(random::<usize>() % 10);
         let secret = b"RagnarWasHere";
-        let mut t = t1
+        let t = t1
             .iter()
             .chain(secret)
             .chain(&t2)
@@ -106,11 +220,68 @@ mod test {
 
         // Lower bound on the length of the LCR we are looking for.
         let l = secret.len();
-        let lcr = super::lcr(&mut t, l);
+        let lcr = super::lcr(&t, l, 1);
         assert!(
             lcr >= secret.len(),
             "LCR: {lcr} is not at least secret of length {}",
             secret.len()
         );
     }
+    #[test]
+    fn reverse_ssas() {
+        for len in [10, 100, 1000] {
+            let t = &(0..len).map(|_| random::<u8>() % 4).collect::<Vec<_>>();
+            let rev = &t.iter().rev().copied().collect::<Vec<_>>();
+            let minimizers = (0..len)
+                .filter(|_| random::<f64>() < 0.2)
+                .collect::<Vec<usize>>();
+            if minimizers.is_empty() {
+                continue;
+            }
+            // Sort the reverse text, and map its positions back to the end of the prefixes.
+            let (_, ssa_rev) = super::build_ssas::<usize>(t, minimizers.clone(), 1);
+            let rev_idxs = minimizers.iter().map(|&i| len - i).collect::<Vec<_>>();
+            let expected = Ssa::new(rev, &rev_idxs);
+            assert!(ssa_rev
+                .sa
+                .iter()
+                .copied()
+                .eq(expected.sa.iter().map(|&i| len - i)));
+            assert_eq!(ssa_rev.lcp, expected.lcp);
+
+            let (_, ssa_rev) = super::build_circular_ssas::<usize>(t, minimizers.clone(), 2);
+            let rev_idxs = minimizers
+                .iter()
+                .map(|&i| (len - i) % len)
+                .collect::<Vec<_>>();
+            let expected = Ssa::new_circular(rev, &rev_idxs, 1);
+            assert!(ssa_rev
+                .sa
+                .iter()
+                .copied()
+                .eq(expected.sa.iter().map(|&i| (len - i) % len)));
+            assert_eq!(ssa_rev.lcp, expected.lcp);
+        }
+    }
+
+    #[test]
+    fn circular() {
+        let secret = b"RagnarWasHere";
+        for split in 1..secret.len() {
+            // One copy of the secret spans the end of the text.
+            let t = secret[split..]
+                .iter()
+                .chain(&gen(20))
+                .chain(secret)
+                .chain(&gen(20))
+                .chain(&secret[..split])
+                .cloned()
+                .collect::<Vec<_>>();
+            let lcr = super::lcr_circular(&t, secret.len(), 1);
+            assert!(lcr >= secret.len(), "LCR: {lcr} for split {split}");
+        }
+        // A periodic text repeats itself entirely.
+        let t = b"ABCDEFGH".repeat(4);
+        assert_eq!(super::lcr_circular(&t, 6, 1), 32);
+    }
 }
@@ -1,66 +1,357 @@
 #![feature(impl_trait_in_assoc_type, slice_group_by)]
 
+pub mod bounded;
+pub mod bucketed;
+pub mod builder;
+pub mod circular;
+pub mod dense;
+pub mod deterministic;
+pub mod file;
+pub mod gssa;
+pub mod index;
 pub mod lcr;
+pub mod mem;
+pub mod merge;
 mod minimizers;
 pub mod rolling_hash;
+mod search;
+pub mod sorted;
+pub mod suffix_tree;
+pub mod verify;
 
-use std::cmp::{max, min};
+use std::{
+    cmp::{max, min, Ordering},
+    fmt::Debug,
+    hash::Hash,
+    ops::Range,
+};
 
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
 use rdst::{RadixKey, RadixSort};
 use rolling_hash::Mod;
 
-use crate::rolling_hash::RollingHash;
+use crate::{
+    builder::{BuildStats, SsaError},
+    rolling_hash::RollingHash,
+    search::lce_suffixes,
+};
+
+mod sealed {
+    /// Implemented only for `usize` and `u32`, so that `Idx` can not be implemented outside this crate.
+    pub trait Sealed {}
+    impl Sealed for usize {}
+    impl Sealed for u32 {}
+}
+
+/// Integer type used to store text positions and LCPs.
+/// Using `u32` instead of `usize` halves the memory usage for texts up to 4GB.
+/// Sealed: SSA files are read and written as raw arrays of `Idx`, which is only sound for plain integers.
+pub trait Idx:
+    sealed::Sealed + Copy + Ord + Default + Hash + Debug + TryInto<usize> + Send + Sync + 'static
+{
+    const MAX: Self;
+    /// Panics when `x` does not fit.
+    fn from_usize(x: usize) -> Self;
+    fn as_usize(self) -> usize;
+}
+
+impl Idx for usize {
+    const MAX: Self = usize::MAX;
+    #[inline]
+    fn from_usize(x: usize) -> Self {
+        x
+    }
+    #[inline]
+    fn as_usize(self) -> usize {
+        self
+    }
+}
+
+impl Idx for u32 {
+    const MAX: Self = u32::MAX;
+    #[inline]
+    fn from_usize(x: usize) -> Self {
+        x.try_into().expect("Index does not fit in u32.")
+    }
+    #[inline]
+    fn as_usize(self) -> usize {
+        self as usize
+    }
+}
+
+/// Whether a text of length `n` with `b` sampled suffixes can be indexed using `I`.
+/// During construction, groups of suffixes are temporarily referred to by indices
+/// past the end of the text, which need `n + 1 + 4b` values.
+pub fn index_fits<I: Idx>(n: usize, b: usize) -> bool {
+    n.checked_add(1 + 4 * b)
+        .is_some_and(|x| x <= I::MAX.as_usize())
+}
 
 /// A sparse suffix array.
 /// Implementation based on https://arxiv.org/pdf/2310.09023.pdf
 /// C++ code at https://github.com/lorrainea/SSA/blob/main/PA/ssa.cc
-pub struct Ssa {
-    pub sa: Vec<usize>,
-    pub lcp: Vec<usize>,
+pub struct Ssa<I = usize> {
+    pub sa: Vec<I>,
+    pub lcp: Vec<I>,
+}
+
+/// How the end of the text compares to characters, i.e. how a suffix is ordered relative to
+/// its extensions. The end of the text is a unique virtual terminator, so it never contributes
+/// to an LCP.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum EndOfText {
+    /// The end sorts before all characters, as with a `$` sentinel, so that a suffix sorts
+    /// before its extensions. This is the order of most suffix array tools.
+    #[default]
+    Smallest,
+    /// The end sorts after all characters, so that a suffix sorts after its extensions.
+    Largest,
+}
+
+impl EndOfText {
+    /// The character used to show the terminator in `Ssa::print_with`.
+    fn symbol(self) -> char {
+        match self {
+            EndOfText::Smallest => '$',
+            EndOfText::Largest => '~',
+        }
+    }
 }
 
+/// Compare the suffixes of `t` starting at `a` and `b`.
+fn cmp_suffixes(t: &[u8], a: usize, b: usize, end: EndOfText) -> Ordering {
+    let l = lcp(t, a, b);
+    match (t.get(a + l), t.get(b + l)) {
+        (Some(x), Some(y)) => x.cmp(y),
+        (None, None) => Ordering::Equal,
+        (None, Some(_)) if end == EndOfText::Smallest => Ordering::Less,
+        (Some(_), None) if end == EndOfText::Largest => Ordering::Less,
+        _ => Ordering::Greater,
+    }
+}
+
+/// Packed to 12 bytes for `I = u32`.
+/// NOTE: Fields must be copied out before use, since references to them may be unaligned.
 #[derive(Debug, Clone, Copy)]
-struct IH {
+#[repr(C, packed(4))]
+struct IH<I> {
     /// Index in the original text.
-    idx: usize,
+    idx: I,
     /// Hash of a prefix-extension of the suffix starting at `i`.
     h: Mod,
 }
 
+impl<I: Idx> IH<I> {
+    #[inline]
+    fn idx(&self) -> usize {
+        self.idx.as_usize()
+    }
+    #[inline]
+    fn h(&self) -> Mod {
+        self.h
+    }
+}
+
 /// Sort `IH` by the contained hash.
-impl RadixKey for IH {
+impl<I: Idx> RadixKey for IH<I> {
     const LEVELS: usize = 8;
     #[inline]
     fn get_level(&self, level: usize) -> u8 {
-        (self.h.0 >> (level * 8)) as u8
+        (self.h().0 >> (level * 8)) as u8
+    }
+}
+
+/// The strings that are sorted.
+#[derive(Clone, Copy)]
+pub(crate) enum Suffixes<'a> {
+    /// Suffixes of a single text, with the given order of its end.
+    Text(EndOfText),
+    /// Suffixes, which end at the end of their record.
+    /// Contains the exclusive end of each record in the text. A single text is a single record.
+    Records(&'a [usize]),
+    /// Rotations of the text.
+    Circular,
+    /// Reverse prefixes ending at the given positions, i.e. suffixes of the reverse text,
+    /// with the given order of the start of the text.
+    Prefixes(EndOfText),
+    /// Reverse rotations ending just before the given positions, i.e. rotations of the reverse text.
+    CircularPrefixes,
+}
+
+/// Hashes substrings of suffixes or rotations.
+struct RecordHasher<'a> {
+    hasher: &'a RollingHash<'a>,
+    suffixes: Suffixes<'a>,
+}
+
+impl RecordHasher<'_> {
+    /// Hash of `range`, as a substring of the suffix starting at `idx`.
+    #[inline]
+    fn query(&self, idx: usize, range: Range<usize>) -> Mod {
+        match self.suffixes {
+            Suffixes::Text(end) => self.hasher.query_end(range, end),
+            Suffixes::Records([_]) => self.hasher.query(range),
+            Suffixes::Records(ends) => {
+                let record = ends.partition_point(|&end| end <= idx);
+                self.hasher
+                    .query_record(range, ends[record], record, ends.len())
+            }
+            Suffixes::Circular => self.hasher.query_circular(idx, range),
+            Suffixes::Prefixes(end) => {
+                self.hasher
+                    .query_rev(idx, range.start - idx..range.end - idx, end)
+            }
+            Suffixes::CircularPrefixes => self
+                .hasher
+                .query_circular_rev(idx, range.start - idx..range.end - idx),
+        }
     }
 }
 
-fn group_len(v: &mut [IH], i: usize) -> usize {
-    v[i..].iter().take_while(|x| x.h == v[i].h).count()
+fn group_len<I: Idx>(v: &mut [IH<I>], i: usize) -> usize {
+    v[i..].iter().take_while(|x| x.h() == v[i].h()).count()
+}
+
+/// Groups smaller than this are always sorted by the current thread.
+const PAR_MIN_LEN: usize = 1 << 10;
+
+/// Groups whose suffixes share fewer characters are not checked for periodicity.
+const PERIODIC_MIN_LCP: usize = 32;
+
+/// Sort by hash, using the multi-threaded radix sort when `par` is set.
+fn sort_hashes<I: Idx>(starts: &mut [IH<I>], par: bool) {
+    if par && starts.len() >= PAR_MIN_LEN {
+        starts.radix_sort_builder().sort();
+    } else {
+        starts
+            .radix_sort_builder()
+            .with_single_threaded_tuner()
+            .sort();
+    }
 }
 
-impl Ssa {
-    pub fn new(t: &[u8], idxs: &Vec<usize>) -> Self {
+impl<I: Idx> Ssa<I> {
+    pub fn new(t: &[u8], idxs: &[I]) -> Self {
         Self::new_params(t, idxs, Some(idxs.len().ilog2() as _), true)
     }
-    pub fn new_params(t: &[u8], idxs: &Vec<usize>, l0: Option<usize>, exp_search: bool) -> Self {
+    /// Like `new`, but sort independent groups on `threads` threads.
+    /// `threads == 0` uses all available cores.
+    pub fn new_par(t: &[u8], idxs: &[I], threads: usize) -> Self {
+        Self::new_params_par(t, idxs, Some(idxs.len().ilog2() as _), true, threads)
+    }
+    pub fn new_params(t: &[u8], idxs: &[I], l0: Option<usize>, exp_search: bool) -> Self {
+        Self::new_params_par(t, idxs, l0, exp_search, 1)
+    }
+    /// The output is identical to `new_params`, independent of the number of threads.
+    pub fn new_params_par(
+        t: &[u8],
+        idxs: &[I],
+        l0: Option<usize>,
+        exp_search: bool,
+        threads: usize,
+    ) -> Self {
+        let start = std::time::Instant::now();
+        let hasher = RollingHash::new(t, hasher_sampling(t.len()));
+        eprintln!("Hasher done in {:?}", start.elapsed());
+        Self::new_with_hasher(
+            t,
+            Suffixes::Text(EndOfText::Smallest),
+            idxs,
+            l0,
+            exp_search,
+            threads,
+            &hasher,
+            &mut BuildStats::default(),
+        )
+    }
+
+    /// Las Vegas variant of `new_params_par`: the result is checked using `check`, and rebuilt
+    /// with a freshly randomised hash function until it passes.
+    /// Also returns the number of retries that were needed.
+    /// The positions are checked first as in `SsaBuilder`, since invalid positions would never pass.
+    pub fn new_las_vegas(
+        t: &[u8],
+        idxs: &[I],
+        l0: Option<usize>,
+        exp_search: bool,
+        threads: usize,
+    ) -> Result<(Self, usize), SsaError> {
+        builder::check_positions(t.len(), &mut idxs.to_vec())?;
+        let s = hasher_sampling(t.len());
+        let mut hasher = RollingHash::new(t, s);
+        let mut retries = 0;
+        loop {
+            let ssa = Self::new_with_hasher(
+                t,
+                Suffixes::Text(EndOfText::Smallest),
+                idxs,
+                l0,
+                exp_search,
+                threads,
+                &hasher,
+                &mut BuildStats::default(),
+            );
+            if ssa.check(t) {
+                return Ok((ssa, retries));
+            }
+            eprintln!("Hash collision detected; rebuilding with a random hash.");
+            retries += 1;
+            hasher = RollingHash::new_random(t, s);
+        }
+    }
+
+    /// Sorts the given `suffixes` of `t` starting at `idxs`.
+    /// Counters and the sorting time are added to `stats`.
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn new_with_hasher(
+        t: &[u8],
+        suffixes: Suffixes,
+        idxs: &[I],
+        l0: Option<usize>,
+        exp_search: bool,
+        threads: usize,
+        hasher: &RollingHash,
+        stats: &mut BuildStats,
+    ) -> Self {
+        Self::new_bounded_with_hasher(
+            t,
+            suffixes,
+            idxs,
+            l0,
+            exp_search,
+            threads,
+            hasher,
+            usize::MAX,
+            stats,
+        )
+    }
+
+    /// Like `new_with_hasher`, but only sorts by the first `max_lcp` characters.
+    /// LCPs are capped at `max_lcp`, and suffixes sharing that many characters are left in arbitrary order.
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn new_bounded_with_hasher(
+        t: &[u8],
+        suffixes: Suffixes,
+        idxs: &[I],
+        l0: Option<usize>,
+        exp_search: bool,
+        threads: usize,
+        hasher: &RollingHash,
+        max_lcp: usize,
+        stats: &mut BuildStats,
+    ) -> Self {
         assert!(!idxs.is_empty());
         let n = t.len();
+        assert!(
+            index_fits::<I>(n, idxs.len()),
+            "Text too long for index type."
+        );
         let l0 = match (l0, exp_search) {
             (Some(max_l), _) => max_l.next_power_of_two(),
             (None, false) => 1 << n.ilog2(),
             (None, true) => (idxs.len().ilog2() / 2).next_power_of_two() as _,
         };
         let b = idxs.len();
-        let s = if n == 1 {
-            8
-        } else {
-            (n / n.ilog2() as usize).next_power_of_two().max(8)
-        };
-        let start = std::time::Instant::now();
-        let hasher = rolling_hash::RollingHash::new(t, s);
-        eprintln!("Hasher done in {:?}", start.elapsed());
 
         let mut starts = idxs
             .iter()
@@ -70,92 +361,175 @@ impl Ssa {
             })
             .collect::<Vec<_>>();
 
-        let mut lcp = vec![usize::MAX; b - 1];
+        let mut lcp = vec![I::MAX; b - 1];
 
         // Contains [witness, length, idxs...] for each group consecutively.
         let mut cache = vec![];
 
-        fn witness(idx: usize, t: &[u8], cache: &Vec<usize>) -> usize {
+        fn witness<I: Idx>(idx: usize, t: &[u8], cache: &[I]) -> usize {
             if idx <= t.len() {
                 idx
             } else {
-                cache[idx - t.len() - 1]
+                cache[idx - t.len() - 1].as_usize()
             }
         }
 
-        /// Given a slice of indices that already have the given lcp, sort them in-place and write the LCP array.
-        fn dfs(
+        /// The state of a call to `dfs` on `starts[range]`.
+        struct Frame {
+            range: Range<usize>,
             l: usize,
             exp_search: bool,
+            par: bool,
             group_lcp: usize,
+            depth: usize,
+            /// LCPs are capped at this length, and groups sharing it are not refined further.
+            max_lcp: usize,
+        }
+
+        /// Work items on the explicit stack of `dfs`, so that deep chains of groups do not overflow the call stack.
+        enum Task {
+            /// Steps 1-3: group by the next `l` characters and sort the main array.
+            Sort(Frame),
+            /// Steps 4-5: once the first `num_groups` elements of the main array are sorted,
+            /// insert the groups cached after `old_cache_len` back and sort them.
+            Reinsert {
+                frame: Frame,
+                num_groups: usize,
+                old_cache_len: usize,
+            },
+        }
+
+        /// Given a slice of indices that already have the given lcp, sort them in-place and write the LCP array.
+        /// `par` may only be set when `starts` contains no references into `cache`,
+        /// so that the groups in step 5 can be sorted independently with their own cache.
+        /// Sub-problems are kept on an explicit stack, so the recursion depth only grows with nested parallel groups.
+        fn dfs<I: Idx>(
+            frame: Frame,
+            t: &[u8],
+            hasher: &RecordHasher,
+            starts: &mut [IH<I>],
+            cache: &mut Vec<I>,
+            lcp_out: &mut [I],
+            stats: &mut BuildStats,
+        ) {
+            assert!(lcp_out.len() + 1 == starts.len());
+            let mut stack = vec![Task::Sort(frame)];
+            while let Some(task) = stack.pop() {
+                match task {
+                    Task::Sort(frame) => {
+                        sort(frame, t, hasher, starts, cache, lcp_out, &mut stack, stats)
+                    }
+                    Task::Reinsert {
+                        frame,
+                        num_groups,
+                        old_cache_len,
+                    } => reinsert(
+                        frame,
+                        num_groups,
+                        old_cache_len,
+                        t,
+                        hasher,
+                        starts,
+                        cache,
+                        lcp_out,
+                        &mut stack,
+                        stats,
+                    ),
+                }
+            }
+        }
+
+        #[allow(clippy::too_many_arguments)]
+        fn sort<I: Idx>(
+            frame: Frame,
             t: &[u8],
-            hasher: &RollingHash,
-            starts: &mut [IH],
-            cache: &mut Vec<usize>,
-            lcp_out: &mut [usize],
+            hasher: &RecordHasher,
+            starts: &mut [IH<I>],
+            cache: &mut Vec<I>,
+            lcp_out: &mut [I],
+            stack: &mut Vec<Task>,
+            stats: &mut BuildStats,
         ) {
-            let n = starts.len();
-            assert!(lcp_out.len() == n - 1);
+            let Frame {
+                ref range,
+                l,
+                exp_search,
+                par,
+                group_lcp,
+                depth,
+                max_lcp,
+            } = frame;
+            let n = range.len();
+            stats.max_depth = stats.max_depth.max(depth);
             if n <= 1 {
                 return;
             }
+            let starts = &mut starts[range.clone()];
+            let lcp_out = &mut lcp_out[range.start..range.end - 1];
+
+            if group_lcp >= max_lcp {
+                // Ties are left unsorted.
+                lcp_out.fill(I::from_usize(max_lcp));
+                return;
+            }
+
+            if group_lcp >= PERIODIC_MIN_LCP
+                && split_periodic(&frame, t, hasher, starts, lcp_out, stack, stats)
+            {
+                return;
+            }
 
             if l == 0 {
                 // Simply sort the groups by their next character.
-                lcp_out.fill(group_lcp);
+                lcp_out.fill(I::from_usize(group_lcp));
 
-                for IH { idx, h } in starts.iter_mut() {
-                    let idx = witness(*idx, t, cache);
-                    *h = hasher.query(idx + group_lcp..idx + group_lcp + 1);
+                for x in starts.iter_mut() {
+                    let idx = witness(x.idx(), t, cache);
+                    x.h = hasher.query(idx, idx + group_lcp..idx + group_lcp + 1);
                 }
-                starts
-                    .radix_sort_builder()
-                    .with_single_threaded_tuner()
-                    .sort();
+                stats.hash_queries += n;
+                sort_hashes(starts, par);
                 return;
             }
 
             assert!(l.is_power_of_two(), "{l} is not a power of two");
+            // Do not compare characters beyond `max_lcp`.
+            let l = min(l, 1 << (max_lcp - group_lcp).ilog2());
 
             // TODO: Resolve size-2 groups using LCP.
 
             // Group start..end by their 2^len prefix extension.
             // First, compute hashes.
-            for IH { idx, h } in starts.iter_mut() {
-                let idx = witness(*idx, t, cache);
-                *h = hasher.query(idx + group_lcp..idx + group_lcp + l);
+            for x in starts.iter_mut() {
+                let idx = witness(x.idx(), t, cache);
+                x.h = hasher.query(idx, idx + group_lcp..idx + group_lcp + l);
             }
+            stats.hash_queries += n;
             // Second, sort by hashes.
             // starts.sort_by_key(|h| h.h.0);
-            starts
-                .radix_sort_builder()
-                .with_single_threaded_tuner()
-                .sort();
+            sort_hashes(starts, par);
             // Third, count groups.
-            let num_groups = starts.group_by(|a, b| a.h == b.h).count();
+            let num_groups = starts.group_by(|a, b| a.h() == b.h()).count();
+            stats.add_groups(depth, num_groups);
             // Fourth, recurse into groups.
             if num_groups == 1 {
-                // One big group: Recurse with increased LCP length.
-                dfs(
-                    if exp_search {
-                        // eprintln!("L {}", l * 2);
-                        l * 2
-                    } else {
-                        l / 2
-                    },
-                    exp_search,
-                    group_lcp + l,
-                    t,
-                    hasher,
-                    starts,
-                    cache,
-                    lcp_out,
-                );
+                // One big group: Continue with increased LCP length.
+                stack.push(Task::Sort(Frame {
+                    l: if exp_search { l * 2 } else { l / 2 },
+                    group_lcp: group_lcp + l,
+                    depth: depth + 1,
+                    ..frame
+                }));
                 return;
             }
             if num_groups == n {
-                // All groups are singletons: Recurse with original LCP length.
-                dfs(l / 2, false, group_lcp, t, hasher, starts, cache, lcp_out);
+                // All groups are singletons: Continue with original LCP length.
+                stack.push(Task::Sort(Frame {
+                    l: l / 2,
+                    exp_search: false,
+                    depth: depth + 1,
+                    ..frame
+                }));
                 return;
             }
             // Otherwise:
@@ -177,42 +551,163 @@ impl Ssa {
                     starts[j] = starts[i];
                 } else {
                     let group_idx = t.len() + 1 + cache.len();
-                    let witness = witness(starts[i].idx, t, cache);
+                    let witness = witness(starts[i].idx(), t, cache);
                     // 1. Move groups to the cache.
-                    cache.push(witness);
-                    cache.push(group_len);
+                    cache.push(I::from_usize(witness));
+                    cache.push(I::from_usize(group_len));
                     cache.extend(starts[i..i + group_len].iter().map(|x| x.idx));
                     // 2. Write sentinel to main array.
-                    starts[j].idx = group_idx;
+                    starts[j].idx = I::from_usize(group_idx);
                 }
+                stats.cache_peak = stats.cache_peak.max(cache.len());
                 i += group_len;
                 j += 1;
             }
             assert_eq!(i, n);
             assert_eq!(j, num_groups);
 
-            // 3. Recursively sort the main array of LCPs that grow less than l.
-            dfs(
-                l / 2,
-                false,
+            // 3. Sort the main array of LCPs that grow less than l, before steps 4 and 5.
+            // The main array contains sentinels, so it can not be split over threads.
+            let main = Frame {
+                range: range.start..range.start + j,
+                l: l / 2,
+                exp_search: false,
+                par: false,
                 group_lcp,
-                t,
-                hasher,
-                &mut starts[..j],
-                cache,
-                &mut lcp_out[..j - 1],
-            );
+                depth: depth + 1,
+                max_lcp,
+            };
+            stack.push(Task::Reinsert {
+                frame: Frame { l, ..frame },
+                num_groups,
+                old_cache_len,
+            });
+            stack.push(Task::Sort(main));
+        }
+
+        /// When two suffixes in `starts` begin `p <= group_lcp / 2` apart, their common prefix has period `p`.
+        /// Each suffix then consists of `r >= group_lcp` characters of this periodic string,
+        /// followed by a character that is smaller or larger than the next one in the period, or by the end of the text.
+        /// Suffixes that differ in `r` or in this direction are ordered directly, with LCP the smaller `r`,
+        /// and only groups with equal `r` and direction are sorted further.
+        /// Returns `false` when there is no such `p`.
+        fn split_periodic<I: Idx>(
+            frame: &Frame,
+            t: &[u8],
+            hasher: &RecordHasher,
+            starts: &mut [IH<I>],
+            lcp_out: &mut [I],
+            stack: &mut Vec<Task>,
+            stats: &mut BuildStats,
+        ) -> bool {
+            let Suffixes::Text(end) = hasher.suffixes else {
+                return false;
+            };
+            let (n, group_lcp) = (t.len(), frame.group_lcp);
+            if starts.iter().any(|x| x.idx() > n) {
+                return false;
+            }
+            // Sort by position to find the closest pair.
+            for x in starts.iter_mut() {
+                x.h = Mod(x.idx() as u64);
+            }
+            sort_hashes(starts, frame.par);
+            let p = starts
+                .windows(2)
+                .map(|w| w[1].idx() - w[0].idx())
+                .min()
+                .unwrap();
+            if 2 * p > group_lcp {
+                return false;
+            }
+
+            // The run containing `i` ends at `e`, where `t[e] != t[e-p]`. Suffixes after `i` share it while `i+p <= e`.
+            // Suffixes breaking downwards sort by increasing `r = e-i`, followed by those breaking upwards by decreasing `r`.
+            let mut e = 0;
+            for x in starts.iter_mut() {
+                let i = x.idx();
+                if i + p > e {
+                    e = i
+                        + group_lcp
+                        + lce_suffixes(hasher.hasher, i + group_lcp - p, i + group_lcp);
+                }
+                let r = e - i;
+                let down = match t.get(e) {
+                    Some(&c) => c < t[e - p],
+                    None => end == EndOfText::Smallest,
+                };
+                x.h = Mod(if down { r } else { 2 * n + 1 - r } as u64);
+            }
+            sort_hashes(starts, frame.par);
+            let run_len = |h: Mod| min(h.0 as usize, 2 * n + 1 - h.0 as usize);
+
+            let mut i = 0;
+            let mut num_groups = 0;
+            while i < starts.len() {
+                let group_len = group_len(starts, i);
+                let r = run_len(starts[i].h());
+                if i > 0 {
+                    let lcp = min(run_len(starts[i - 1].h()), r);
+                    lcp_out[i - 1] = I::from_usize(min(lcp, frame.max_lcp));
+                }
+                if group_len > 1 {
+                    stack.push(Task::Sort(Frame {
+                        range: frame.range.start + i..frame.range.start + i + group_len,
+                        l: frame.l,
+                        exp_search: frame.exp_search,
+                        par: frame.par,
+                        group_lcp: r,
+                        depth: frame.depth + 1,
+                        max_lcp: frame.max_lcp,
+                    }));
+                }
+                i += group_len;
+                num_groups += 1;
+            }
+            stats.add_groups(frame.depth, num_groups);
+            stats.periodic_groups += 1;
+            true
+        }
+
+        #[allow(clippy::too_many_arguments)]
+        fn reinsert<I: Idx>(
+            frame: Frame,
+            num_groups: usize,
+            old_cache_len: usize,
+            t: &[u8],
+            hasher: &RecordHasher,
+            starts: &mut [IH<I>],
+            cache: &mut Vec<I>,
+            lcp_out: &mut [I],
+            stack: &mut Vec<Task>,
+            stats: &mut BuildStats,
+        ) {
+            let Frame {
+                ref range,
+                l,
+                exp_search,
+                par,
+                group_lcp,
+                depth,
+                max_lcp,
+            } = frame;
+            let n = range.len();
+            let offset = range.start;
+            let starts = &mut starts[range.clone()];
+            let lcp_out = &mut lcp_out[range.start..range.end - 1];
 
             // 4. Insert cached groups back into the main array.
+            let mut i = n;
+            let mut j = num_groups;
             while j > 0 {
                 j -= 1;
-                if starts[j].idx < t.len() + 1 + old_cache_len {
+                if starts[j].idx() < t.len() + 1 + old_cache_len {
                     i -= 1;
                     starts[i].idx = starts[j].idx;
                     starts[i].h = Mod::NONE;
                 } else {
-                    let cache_idx = starts[j].idx - t.len() - 1;
-                    let group_len = cache[cache_idx + 1];
+                    let cache_idx = starts[j].idx() - t.len() - 1;
+                    let group_len = cache[cache_idx + 1].as_usize();
                     i -= group_len;
                     for k in 0..group_len {
                         starts[i + k].idx = cache[cache_idx + 2 + k];
@@ -225,44 +720,120 @@ impl Ssa {
             }
             assert_eq!(i, 0);
 
-            cache.shrink_to(old_cache_len);
+            // The cached groups are not referenced anymore.
+            cache.truncate(old_cache_len);
 
             // 5. Recurse on the groups that grow more than l.
+            let child_l = if exp_search { l * 2 } else { l / 2 };
+            if par && n >= PAR_MIN_LEN {
+                // The groups are disjoint and only contain original indices,
+                // so each can be sorted on its own thread with a fresh cache.
+                let mut groups = vec![];
+                let mut rest = (&mut starts[..], &mut lcp_out[..]);
+                while !rest.0.is_empty() {
+                    let (s, lcps) = rest;
+                    let group_len = if s[0].h() == Mod::NONE {
+                        1
+                    } else {
+                        group_len(s, 0)
+                    };
+                    let (group, s) = s.split_at_mut(group_len);
+                    let (group_lcp_out, lcps) = lcps.split_at_mut(group_len - 1);
+                    // Skip the LCP between this group and the next.
+                    let lcps = if lcps.is_empty() {
+                        lcps
+                    } else {
+                        &mut lcps[1..]
+                    };
+                    if group_len > 1 {
+                        groups.push((group, group_lcp_out));
+                    }
+                    rest = (s, lcps);
+                }
+                let group_stats = groups
+                    .into_par_iter()
+                    .map(|(group, group_lcp_out)| {
+                        let mut stats = BuildStats::default();
+                        dfs(
+                            Frame {
+                                range: 0..group.len(),
+                                l: child_l,
+                                exp_search,
+                                par: true,
+                                group_lcp: group_lcp + l,
+                                depth: depth + 1,
+                                max_lcp,
+                            },
+                            t,
+                            hasher,
+                            group,
+                            &mut vec![],
+                            group_lcp_out,
+                            &mut stats,
+                        );
+                        stats
+                    })
+                    .reduce(BuildStats::default, |mut a, b| {
+                        a.merge(&b);
+                        a
+                    });
+                stats.merge(&group_stats);
+                return;
+            }
+            // Push the groups in reverse, so that they are sorted from left to right.
+            let mut groups = vec![];
             while i < n {
-                if starts[i].h == Mod::NONE {
+                if starts[i].h() == Mod::NONE {
                     i += 1;
                     continue;
                 }
                 let group_len = group_len(starts, i);
-                dfs(
-                    if exp_search {
-                        // eprintln!("L {}", l * 2);
-                        l * 2
-                    } else {
-                        l / 2
-                    },
+                groups.push(Task::Sort(Frame {
+                    range: offset + i..offset + i + group_len,
+                    l: child_l,
                     exp_search,
-                    group_lcp + l,
-                    t,
-                    hasher,
-                    &mut starts[i..i + group_len],
-                    cache,
-                    &mut lcp_out[i..i + group_len - 1],
-                );
+                    par,
+                    group_lcp: group_lcp + l,
+                    depth: depth + 1,
+                    max_lcp,
+                }));
                 i += group_len;
             }
+            stack.extend(groups.into_iter().rev());
         }
 
-        dfs(
-            l0,
-            exp_search,
-            0,
-            t,
-            &hasher,
-            &mut starts,
-            &mut cache,
-            &mut lcp,
-        );
+        let hasher = &RecordHasher { hasher, suffixes };
+        let par = threads != 1;
+        let start = std::time::Instant::now();
+        let mut run = || {
+            dfs(
+                Frame {
+                    range: 0..b,
+                    l: l0,
+                    exp_search,
+                    par,
+                    group_lcp: 0,
+                    depth: 0,
+                    max_lcp,
+                },
+                t,
+                hasher,
+                &mut starts,
+                &mut cache,
+                &mut lcp,
+                stats,
+            )
+        };
+        if par {
+            rayon::ThreadPoolBuilder::new()
+                .num_threads(threads)
+                .build()
+                .unwrap()
+                .install(run);
+        } else {
+            run();
+        }
+        stats.sort_time += start.elapsed();
 
         Self {
             sa: starts.iter().map(|x| x.idx).collect(),
@@ -270,21 +841,33 @@ impl Ssa {
         }
     }
 
+    /// Cheap randomized check of the SA and LCP arrays. See `verify_hashed`.
+    /// Returns false if an error was found.
+    pub fn check(&self, t: &[u8]) -> bool {
+        self.verify_hashed(t, 1).is_ok()
+    }
+
+    /// Naive check of the SA and LCP arrays, comparing full suffixes.
+    /// This is quadratic on repetitive input; prefer `verify_hashed` for large texts.
     pub fn verify(&self, t: &[u8]) {
+        self.verify_with(t, EndOfText::Smallest)
+    }
+    /// Like `verify`, with the given order of the end of the text.
+    pub fn verify_with(&self, t: &[u8], end: EndOfText) {
         let b = self.sa.len();
         assert_eq!(self.lcp.len(), b - 1);
         for i in 0..b - 1 {
-            if t[self.sa[i]..] > t[self.sa[i + 1]..] {
-                assert!(
-                    t[self.sa[i]..] > t[self.sa[i + 1]..],
-                    "Bad order at position {i} with LCP {}. Prefixes:\n{}\n{}",
+            let (sa0, sa1) = (self.sa[i].as_usize(), self.sa[i + 1].as_usize());
+            if cmp_suffixes(t, sa0, sa1, end) != Ordering::Less {
+                panic!(
+                    "Bad order at position {i} with LCP {:?}. Prefixes:\n{}\n{}",
                     self.lcp[i],
-                    t[self.sa[i]..]
+                    t[sa0..]
                         .iter()
                         .take(100)
                         .map(|&x| x as char)
                         .collect::<String>(),
-                    t[self.sa[i + 1]..]
+                    t[sa1..]
                         .iter()
                         .take(100)
                         .map(|&x| x as char)
@@ -292,25 +875,49 @@ impl Ssa {
                 );
             }
             assert_eq!(
-                lcp(t, self.sa[i], self.sa[i + 1]),
-                self.lcp[i],
-                "Bad LCP at position {i}. Got {}",
+                lcp(t, sa0, sa1),
+                self.lcp[i].as_usize(),
+                "Bad LCP at position {i}. Got {:?}",
                 self.lcp[i]
             );
         }
     }
     pub fn print(&self, t: &[u8]) {
-        eprintln!("{}", std::str::from_utf8(t).unwrap());
+        self.print_with(t, EndOfText::Smallest)
+    }
+    /// Like `print`, showing the end of the text as `$` when it is smallest and `~` when it is largest.
+    pub fn print_with(&self, t: &[u8], end: EndOfText) {
+        eprintln!("{}{}", std::str::from_utf8(t).unwrap(), end.symbol());
         for i in 0..self.sa.len() {
-            let j = self.sa[i];
-            let lcp = self.lcp.get(i).copied().unwrap_or_default();
-            let lcp2 = self.lcp.get(i.wrapping_sub(1)).copied().unwrap_or_default();
-            let pref = std::str::from_utf8(&t[j..min(j + max(lcp, lcp2) + 1, t.len())]).unwrap();
-            eprintln!("{i:>3} {j:>3} {lcp:>3} {pref}");
+            let j = self.sa[i].as_usize();
+            let lcp = self.lcp.get(i).map_or(0, |x| x.as_usize());
+            let lcp2 = self.lcp.get(i.wrapping_sub(1)).map_or(0, |x| x.as_usize());
+            let pref_end = j + max(lcp, lcp2) + 1;
+            let pref = std::str::from_utf8(&t[j..min(pref_end, t.len())]).unwrap();
+            if pref_end > t.len() {
+                eprintln!("{i:>3} {j:>3} {lcp:>3} {pref}{}", end.symbol());
+            } else {
+                eprintln!("{i:>3} {j:>3} {lcp:>3} {pref}");
+            }
         }
     }
 }
 
+/// The hasher stores a prefix hash every `s` positions, for `s ~ n / lg n`.
+fn hasher_sampling(n: usize) -> usize {
+    if n == 1 {
+        8
+    } else {
+        (n / n.ilog2() as usize).next_power_of_two().max(8)
+    }
+}
+
+/// The sampling for a hasher answering queries about `b` suffixes.
+/// It stores `O(b)` prefix hashes, so that each query scans `O(n/b)` characters.
+fn sparse_hasher_sampling(n: usize, b: usize) -> usize {
+    (n / b.max(1)).next_power_of_two().max(8)
+}
+
 fn lcp(t: &[u8], a: usize, b: usize) -> usize {
     std::iter::zip(&t[a..], &t[b..])
         .take_while(|(a, b)| a == b)
@@ -353,7 +960,7 @@ mod test {
                         .collect::<Vec<_>>();
                     let idxs = (0..len)
                         .filter(|_| rand::random::<f64>() < fraction)
-                        .collect::<Vec<_>>();
+                        .collect::<Vec<usize>>();
                     if idxs.is_empty() {
                         continue;
                     }
@@ -369,4 +976,169 @@ mod test {
             }
         }
     }
+
+    #[test]
+    fn parallel_ssa() {
+        for &fraction in [0.01, 0.1, 0.5, 1.0].iter() {
+            for len in [1000, 10000, 100000] {
+                let t = &(0..len)
+                    .map(|_| rand::random::<u8>() % 4)
+                    .collect::<Vec<_>>();
+                let idxs = (0..len)
+                    .filter(|_| rand::random::<f64>() < fraction)
+                    .collect::<Vec<usize>>();
+                if idxs.is_empty() {
+                    continue;
+                }
+                let ssa1 = Ssa::new(t, &idxs);
+                for threads in [0, 2, 4] {
+                    let ssa2 = Ssa::new_par(t, &idxs, threads);
+                    assert_eq!(ssa1.sa, ssa2.sa);
+                    assert_eq!(ssa1.lcp, ssa2.lcp);
+                }
+            }
+        }
+        // Repetitive input with large groups.
+        let t = &vec![0; 10000];
+        let idxs = (0..t.len()).collect::<Vec<_>>();
+        let ssa1 = Ssa::new_params(t, &idxs, None, false);
+        let ssa2 = Ssa::new_params_par(t, &idxs, None, false, 4);
+        assert_eq!(ssa1.sa, ssa2.sa);
+        assert_eq!(ssa1.lcp, ssa2.lcp);
+    }
+
+    #[test]
+    fn homopolymer() {
+        // Long runs give the largest nested groups. The nesting depth stays logarithmic,
+        // since `l` doubles while a group does not split and halves afterwards.
+        // Enough positions to sort nested groups in parallel.
+        let t = &vec![b'A'; 1 << 20];
+        let idxs = (0..t.len()).step_by(64).collect::<Vec<_>>();
+        assert!(idxs.len() > PAR_MIN_LEN);
+        for (l0, exp_search) in [(None, true), (None, false), (Some(1), true)] {
+            let ssa = Ssa::new_params(t, &idxs, l0, exp_search);
+            assert!(ssa.sa.iter().eq(idxs.iter().rev()));
+            assert!(ssa.lcp.iter().zip(&ssa.sa).all(|(&l, &i)| l == t.len() - i));
+        }
+        let (ssa, stats) = builder::SsaBuilder::new(t)
+            .positions(idxs.iter().copied())
+            .threads(0)
+            .build()
+            .unwrap();
+        assert!(ssa.sa.iter().eq(idxs.iter().rev()));
+        assert!(!stats.dense);
+        assert!(stats.max_depth <= 2 * 20 + 2);
+    }
+
+    #[test]
+    fn periodic_runs() {
+        for p in [1, 3, 171] {
+            // Tandem repeats with a few mutations, between random flanks.
+            let unit = (0..p).map(|_| rand::random::<u8>() % 4).collect::<Vec<_>>();
+            let mut t = vec![];
+            for _ in 0..4 {
+                t.extend((0..100).map(|_| rand::random::<u8>() % 4));
+                let mut run = unit.repeat(10000 / p);
+                for _ in 0..3 {
+                    let k = rand::random::<usize>() % run.len();
+                    run[k] = rand::random::<u8>() % 4;
+                }
+                t.extend(run);
+            }
+            let t = &t;
+            for step in [1, 13] {
+                let idxs = (0..t.len()).step_by(step).collect::<Vec<_>>();
+                let expected = Ssa::new_dense(t, &idxs);
+                let ssa = Ssa::new(t, &idxs);
+                assert_eq!(ssa.sa, expected.sa, "p {p}");
+                assert_eq!(ssa.lcp, expected.lcp, "p {p}");
+                let ssa = Ssa::new_params(t, &idxs, None, false);
+                assert_eq!(ssa.sa, expected.sa, "p {p}");
+                assert_eq!(ssa.lcp, expected.lcp, "p {p}");
+
+                let (ssa, stats) = builder::SsaBuilder::new(t)
+                    .positions(idxs.iter().copied())
+                    .end_of_text(EndOfText::Largest)
+                    .dense_threshold(f64::INFINITY)
+                    .threads(4)
+                    .build()
+                    .unwrap();
+                // With sparse sampling, same-phase positions may be too far apart to split the run.
+                if step == 1 {
+                    assert!(stats.periodic_groups > 0);
+                }
+                assert!(ssa.verify_hashed_with(t, EndOfText::Largest, 1).is_ok());
+            }
+        }
+    }
+
+    #[test]
+    fn compact_ssa() {
+        assert_eq!(std::mem::size_of::<IH<u32>>(), 12);
+        for &fraction in [0.1, 0.5, 1.0].iter() {
+            for len in [10, 100, 1000, 10000] {
+                let t = &(0..len)
+                    .map(|_| rand::random::<u8>() % 4)
+                    .collect::<Vec<_>>();
+                let idxs = (0..len)
+                    .filter(|_| rand::random::<f64>() < fraction)
+                    .collect::<Vec<usize>>();
+                if idxs.is_empty() {
+                    continue;
+                }
+                let idxs32 = idxs.iter().map(|&i| i as u32).collect::<Vec<_>>();
+                let ssa1 = Ssa::new(t, &idxs);
+                let ssa2 = Ssa::new(t, &idxs32);
+                ssa2.verify(t);
+                assert!(ssa1.sa.iter().map(|&i| i as u32).eq(ssa2.sa));
+                assert!(ssa1.lcp.iter().map(|&i| i as u32).eq(ssa2.lcp));
+            }
+        }
+        assert!(index_fits::<u32>(1000, 100));
+        assert!(!index_fits::<u32>(u32::MAX as usize, 1));
+        assert!(index_fits::<u32>(u32::MAX as usize - 5, 1));
+        assert!(!index_fits::<u32>(u32::MAX as usize - 4, 1));
+    }
+
+    #[test]
+    fn las_vegas() {
+        // Two 16-char strings whose first 8-char words differ by exactly P,
+        // so that their default hashes collide.
+        let mut t = vec![0u8; 64];
+        t[32..40].copy_from_slice(&rolling_hash::P.to_le_bytes());
+        t[8..16].fill(7);
+        t[40..48].fill(7);
+        t[16..32].fill(1);
+        t[48..64].fill(2);
+        let idxs = vec![0usize, 32];
+        let ssa = Ssa::new_params(&t, &idxs, Some(16), false);
+        assert_eq!(ssa.lcp, vec![16]);
+        assert!(!ssa.check(&t));
+
+        let (ssa, retries) = Ssa::new_las_vegas(&t, &idxs, Some(16), false, 1).unwrap();
+        assert!(retries >= 1);
+        ssa.verify(&t);
+        assert!(ssa.check(&t));
+
+        for len in [10, 100, 1000] {
+            let t = &(0..len)
+                .map(|_| rand::random::<u8>() % 4)
+                .collect::<Vec<_>>();
+            let idxs = (0..len).collect::<Vec<usize>>();
+            let (ssa, retries) = Ssa::new_las_vegas(t, &idxs, None, true, 1).unwrap();
+            assert_eq!(retries, 0);
+            ssa.verify(t);
+        }
+
+        // Invalid positions fail instead of retrying forever.
+        let t = b"abracadabra";
+        assert_eq!(
+            Ssa::new_las_vegas(t, &[3usize, 7, 3], None, true, 1).err(),
+            Some(SsaError::Duplicate { idx: 3 })
+        );
+        assert_eq!(
+            Ssa::new_las_vegas(t, &[3usize, 12], None, true, 1).err(),
+            Some(SsaError::OutOfRange { idx: 12, n: 11 })
+        );
+    }
 }
@@ -1,9 +1,18 @@
-use std::{fmt::Write, path::PathBuf};
+use std::{
+    fmt::Write,
+    path::{Path, PathBuf},
+};
 
 use clap::Parser;
 use rand::{seq::SliceRandom, thread_rng};
 use rdst::RadixSort;
-use ssa::{lcr::lcr, Ssa};
+use ssa::{
+    file::{Header, MappedSsa, Meta, Sampling},
+    index_fits,
+    lcr::{build_ssas, find_minimizers, lcr_from_ssas, minimizer_params},
+    rolling_hash::RollingHash,
+    Idx, Ssa,
+};
 
 #[derive(clap::Parser)]
 struct Args {
@@ -12,22 +21,51 @@ struct Args {
     l0: usize,
     #[clap(short, long)]
     exp_search: bool,
+    /// Number of threads used to build the suffix arrays. 0 uses all cores.
+    #[clap(short, long, default_value_t = 1)]
+    threads: usize,
+    /// Load the SSAs from `<index>.fwd.ssa` and `<index>.rev.ssa` when they exist,
+    /// and otherwise build them and save them there.
+    #[clap(long)]
+    index: Option<PathBuf>,
+    /// Print the number of sampled occurrences of each line of this file.
+    #[clap(long)]
+    patterns: Option<PathBuf>,
+    /// Verify the SSAs before computing the LCR.
+    #[clap(long)]
+    verify: bool,
 }
 
 fn main() {
     let args = Args::parse();
     // Read std, drop lines starting with >, and concatenate the rest.
     eprintln!("Reading...");
-    let mut t = std::fs::read_to_string(&args.file)
+    let t = std::fs::read_to_string(&args.file)
         .unwrap()
         .lines()
         .filter(|s| !s.starts_with('>'))
         .map(|s| s.to_ascii_uppercase())
         .collect::<String>();
-    let t = unsafe { t.as_bytes_mut() };
+    let t = t.as_bytes();
     eprintln!("Length: {}", t.len());
 
-    lcr(t, args.l0);
+    match &args.index {
+        Some(index) if index_paths(index).0.exists() => {
+            if Header::read(&index_paths(index).0).unwrap().width == 4 {
+                from_index::<u32>(t, &args, index)
+            } else {
+                from_index::<usize>(t, &args, index)
+            }
+        }
+        _ => {
+            let minimizers = find_minimizers(t, args.l0);
+            if index_fits::<u32>(t.len(), minimizers.len()) {
+                build::<u32>(t, &args, minimizers)
+            } else {
+                build::<usize>(t, &args, minimizers)
+            }
+        }
+    }
 
     // Take a subset of indices with the given density.
     // let all_idxs = (0..t.len()).collect::<Vec<_>>();
@@ -55,6 +93,106 @@ fn main() {
     // plot_lcp(&ssa);
 }
 
+fn index_paths(index: &Path) -> (PathBuf, PathBuf) {
+    (
+        index.with_extension("fwd.ssa"),
+        index.with_extension("rev.ssa"),
+    )
+}
+
+/// Build the SSAs and save them when `--index` is given.
+fn build<I: Idx>(t: &[u8], args: &Args, minimizers: Vec<usize>) {
+    let l0 = Some(minimizers.len().ilog2() as usize);
+    let (ssa, ssa_rev) = build_ssas::<I>(t, minimizers, args.threads);
+    if let Some(index) = &args.index {
+        let (k, w) = minimizer_params(args.l0);
+        let (fwd, rev) = index_paths(index);
+        ssa.save(&fwd, &Meta::new(t, Sampling::Minimizers { k, w }, l0))
+            .unwrap();
+        ssa_rev
+            .save(
+                &rev,
+                &Meta::new(t, Sampling::ReverseMinimizers { k, w }, l0),
+            )
+            .unwrap();
+        eprintln!("Saved index to {fwd:?} and {rev:?}");
+    }
+    if let Some(patterns) = &args.patterns {
+        query_patterns(t, patterns, |h, p| ssa.count(h, p));
+    }
+    if args.verify {
+        verify(t, &ssa, &ssa_rev, args.threads);
+    }
+    lcr_from_ssas(ssa, ssa_rev);
+}
+
+/// Load the SSAs saved by `build`.
+/// Pattern queries run on the memory-mapped arrays. The LCR appends to the LCP arrays,
+/// so it works on owned copies, which are made only after the queries.
+fn from_index<I: Idx>(t: &[u8], args: &Args, index: &Path) {
+    let (fwd, rev) = index_paths(index);
+    let ssa = MappedSsa::<I>::open(&fwd).unwrap();
+    let ssa_rev = MappedSsa::<I>::open(&rev).unwrap();
+    eprintln!("Loaded index from {fwd:?} and {rev:?}");
+    let (meta, meta_rev) = (ssa.header.meta, ssa_rev.header.meta);
+    assert!(
+        meta.matches(t),
+        "Index {fwd:?} was built on a different text."
+    );
+    assert_eq!(
+        (meta.n, meta.checksum),
+        (meta_rev.n, meta_rev.checksum),
+        "Index {rev:?} was built on a different text."
+    );
+    let (k, w) = minimizer_params(args.l0);
+    assert_eq!(meta.sampling, Sampling::Minimizers { k, w });
+    assert_eq!(meta_rev.sampling, Sampling::ReverseMinimizers { k, w });
+
+    if let Some(patterns) = &args.patterns {
+        query_patterns(t, patterns, |h, p| ssa.count(h, p));
+    }
+    let (ssa, ssa_rev) = (ssa.to_ssa(), ssa_rev.to_ssa());
+    if args.verify {
+        verify(t, &ssa, &ssa_rev, args.threads);
+    }
+    lcr_from_ssas(ssa, ssa_rev);
+}
+
+/// Verify the SSAs returned by `build_ssas`.
+fn verify<I: Idx>(t: &[u8], ssa: &Ssa<I>, ssa_rev: &Ssa<I>, threads: usize) {
+    eprintln!("Verify");
+    let start = std::time::Instant::now();
+    let report = ssa.verify_hashed(t, threads);
+    // The reverse SSA stores positions in the forward text.
+    let rev = Ssa {
+        sa: ssa_rev
+            .sa
+            .iter()
+            .map(|&i| I::from_usize(t.len() - i.as_usize()))
+            .collect(),
+        lcp: ssa_rev.lcp.clone(),
+    };
+    let t_rev = t.iter().rev().copied().collect::<Vec<_>>();
+    let report_rev = rev.verify_hashed(&t_rev, threads);
+    eprintln!("\tTime: {:?}", start.elapsed());
+    for report in [report, report_rev] {
+        if !report.is_ok() {
+            eprintln!(
+                "Found {} errors in {} pairs: {:#?}",
+                report.num_failures, report.checked, report.failures
+            );
+        }
+    }
+}
+
+fn query_patterns(t: &[u8], path: &Path, count: impl Fn(&RollingHash, &[u8]) -> usize) {
+    let hasher = RollingHash::new(t, 8);
+    for pattern in std::fs::read_to_string(path).unwrap().lines() {
+        let p = pattern.to_ascii_uppercase();
+        println!("{pattern}\t{}", count(&hasher, p.as_bytes()));
+    }
+}
+
 fn plot_lcp(ssa: &Ssa) {
     let mut lcps = ssa.lcp.clone();
     lcps.radix_sort_unstable();
@@ -0,0 +1,295 @@
+//! Maximal exact matches (MEMs) between an indexed text and a query, as in sparseMEM and essaMEM.
+//!
+//! Let the sparseness `K` be the largest distance from a text position to the next sampled position, inclusive.
+//! Every MEM of length at least `L >= K` then contains a sampled position among its first `K` characters,
+//! from which it matches for at least `L - K + 1` characters.
+//! So for each of the `K` shifts of a MEM, the query suffix at that offset is searched in the SSA,
+//! and each match is extended in both directions.
+//! Matching statistics are found the same way, using that they decrease by at most one per position.
+use std::cmp::{max, min, Ordering};
+
+use crate::{
+    rolling_hash::RollingHash,
+    search::{cmp_suffix, find_range, lce_texts},
+    Idx, Ssa,
+};
+
+/// A maximal exact match between `text[ref_pos..ref_pos + len]` and `query[query_pos..query_pos + len]`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Mem {
+    pub ref_pos: usize,
+    pub query_pos: usize,
+    pub len: usize,
+}
+
+impl<I: Idx> Ssa<I> {
+    /// The sparseness `K` of the sampled positions in a text of length `n`: every `K` consecutive
+    /// positions, and the last `K` positions of the text, contain a sampled position.
+    pub fn sparseness(&self, n: usize) -> usize {
+        let mut idxs = self.sa.iter().map(|i| i.as_usize()).collect::<Vec<_>>();
+        idxs.sort_unstable();
+        sparseness(&idxs, n)
+    }
+
+    /// All MEMs of length at least `min_len` between the text and `query`, sorted by query position.
+    /// Only MEMs containing a sampled position are found, which includes all of them when
+    /// `min_len >= self.sparseness(n)`. Smaller `min_len` make the search slow.
+    /// `text` must be a hasher over the text this SSA was built on. A small sampling, e.g. 8, makes its queries fast.
+    pub fn mems(&self, text: &RollingHash, query: &[u8], min_len: usize) -> Vec<Mem> {
+        let t = text.text();
+        let mut idxs = self.sa.iter().map(|i| i.as_usize()).collect::<Vec<_>>();
+        idxs.sort_unstable();
+        let k = sparseness(&idxs, t.len());
+        // Canonical matches extend at least this far to the right of their sampled position.
+        let min_right = min_len.saturating_sub(k - 1).max(1);
+        let pattern = text.new_same_base(query, 8);
+
+        let mut mems = vec![];
+        for q in 0..query.len().saturating_sub(min_right - 1) {
+            for &r in &self.sa[find_range(&self.sa, text, &pattern, q..q + min_right)] {
+                let r = r.as_usize();
+                // Only report each MEM from the first sampled position it contains, i.e. when it
+                // does not extend left past the previous sampled position.
+                let rank = idxs.partition_point(|&i| i < r);
+                let max_left = if rank == 0 { r } else { r - idxs[rank - 1] - 1 };
+                let mut left = 0;
+                while left <= max_left && left < r.min(q) && t[r - left - 1] == query[q - left - 1]
+                {
+                    left += 1;
+                }
+                if left > max_left {
+                    continue;
+                }
+                let len = left + lce_texts(text, r, &pattern, q);
+                if len >= min_len {
+                    mems.push(Mem {
+                        ref_pos: r - left,
+                        query_pos: q - left,
+                        len,
+                    });
+                }
+            }
+        }
+        mems.sort_unstable_by_key(|m| (m.query_pos, m.ref_pos));
+        mems
+    }
+}
+
+impl<I: Idx> Ssa<I> {
+    /// The matching statistics of `query`: for each position, the length of its longest prefix occurring in the text.
+    /// Values of at least `K = self.sparseness(n)` are exact, since such an occurrence contains a sampled
+    /// position among its first `K` characters. Smaller values are lower bounds, and all values are exact
+    /// when all positions are sampled.
+    /// Each position takes `O(K)` searches in the SA, plus a check of each sampled suffix matching
+    /// at least `K - j` characters at offset `j`, as for `self.mems(text, query, K)`.
+    /// `text` must be a hasher over the text this SSA was built on. A small sampling, e.g. 8, makes its queries fast.
+    pub fn matching_statistics(&self, text: &RollingHash, query: &[u8]) -> Vec<usize> {
+        let t = text.text();
+        let m = query.len();
+        let k = self.sparseness(t.len());
+        let pattern = text.new_same_base(query, 8);
+
+        // For each query position, the sampled suffixes next to it in the SA, with their LCE.
+        // These include the longest match with a sampled suffix.
+        let neighbours = (0..m)
+            .map(|p| {
+                let rank = self.sa.partition_point(|&r| {
+                    cmp_suffix(text, r.as_usize(), &pattern, p..m) == Ordering::Less
+                });
+                let r0 = self.sa[rank.saturating_sub(1)].as_usize();
+                let r1 = self.sa[min(rank, self.sa.len() - 1)].as_usize();
+                [r0, r1].map(|r| (r, lce_texts(text, r, &pattern, p)))
+            })
+            .collect::<Vec<_>>();
+
+        let mut ms = Vec::with_capacity(m);
+        for q in 0..m {
+            // Like a suffix link: dropping the first character of a match leaves a match.
+            let mut l = ms.last().map_or(0, |&l: &usize| l.saturating_sub(1));
+            // A lower bound: extend the longest sampled matches at `q + j` to the left by `j` characters.
+            for j in 0..min(k, m - q) {
+                for &(r, lce) in &neighbours[q + j] {
+                    if j + lce > l && r >= j && text.query(r - j..r) == pattern.query(q..q + j) {
+                        l = j + lce;
+                    }
+                }
+            }
+            // Longer matches of length at least `K` start `j < K` characters before a sampled suffix
+            // that matches at least `len` characters at `q + j`.
+            for j in 0..min(k, m - q) {
+                let len = max(l + 1, k) - j;
+                let longest = neighbours[q + j].iter().map(|&(_, lce)| lce).max().unwrap();
+                if q + j + len > m || longest < len {
+                    continue;
+                }
+                for &r in &self.sa[find_range(&self.sa, text, &pattern, q + j..q + j + len)] {
+                    let r = r.as_usize();
+                    // Compare a single character before hashing the left context.
+                    if r >= j
+                        && (j == 0 || t[r - 1] == query[q + j - 1])
+                        && text.query(r - j..r) == pattern.query(q..q + j)
+                    {
+                        l = max(l, j + lce_texts(text, r, &pattern, q + j));
+                    }
+                }
+            }
+            ms.push(l);
+        }
+        ms
+    }
+}
+
+/// The sparseness of the sorted positions `idxs` in a text of length `n`.
+fn sparseness(idxs: &[usize], n: usize) -> usize {
+    let Some((&first, &last)) = idxs.first().zip(idxs.last()) else {
+        return n + 1;
+    };
+    idxs.windows(2)
+        .map(|w| w[1] - w[0])
+        .chain([first + 1, n - last])
+        .max()
+        .unwrap()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// All MEMs containing a sampled position, by brute force.
+    fn naive_mems(t: &[u8], idxs: &[usize], query: &[u8], min_len: usize) -> Vec<Mem> {
+        let mut mems = vec![];
+        for q in 0..query.len() {
+            for r in 0..t.len() {
+                if r > 0 && q > 0 && t[r - 1] == query[q - 1] {
+                    continue;
+                }
+                let len = t[r..]
+                    .iter()
+                    .zip(&query[q..])
+                    .take_while(|(x, y)| x == y)
+                    .count();
+                if len >= min_len.max(1) && idxs.iter().any(|&i| (r..r + len).contains(&i)) {
+                    mems.push(Mem {
+                        ref_pos: r,
+                        query_pos: q,
+                        len,
+                    });
+                }
+            }
+        }
+        mems
+    }
+
+    #[test]
+    fn small() {
+        let t = b"abracadabra";
+        let idxs = (0..t.len()).collect::<Vec<_>>();
+        let ssa = Ssa::new(t, &idxs);
+        assert_eq!(ssa.sparseness(t.len()), 1);
+        let hasher = RollingHash::new(t, 8);
+        // "abrac" at 0 and "abra" at 7, but not "bra" at 8, which extends left.
+        let mems = ssa.mems(&hasher, b"cabrac", 3);
+        let mems = mems
+            .iter()
+            .map(|m| (m.ref_pos, m.query_pos, m.len))
+            .collect::<Vec<_>>();
+        assert_eq!(mems, vec![(0, 1, 5), (7, 1, 4)]);
+    }
+
+    #[test]
+    fn matching_statistics() {
+        let t = b"abracadabra";
+        let idxs = (0..t.len()).collect::<Vec<_>>();
+        let ssa = Ssa::new(t, &idxs);
+        let hasher = RollingHash::new(t, 8);
+        assert_eq!(
+            ssa.matching_statistics(&hasher, b"cabrabx"),
+            vec![2, 4, 3, 2, 2, 1, 0]
+        );
+
+        for len in [10, 100, 1000, 3000] {
+            let t = &(0..len)
+                .map(|_| rand::random::<u8>() % 4)
+                .collect::<Vec<_>>();
+            let start = rand::random::<usize>() % len;
+            let mut query = t[start..(start + 300).min(len)].to_vec();
+            for _ in 0..query.len() / 20 {
+                let k = rand::random::<usize>() % query.len();
+                query[k] = rand::random::<u8>() % 4;
+            }
+            query.extend((0..50).map(|_| rand::random::<u8>() % 5));
+            let lce = |r: usize, q: usize| {
+                t[r..]
+                    .iter()
+                    .zip(&query[q..])
+                    .take_while(|(x, y)| x == y)
+                    .count()
+            };
+            let naive = (0..query.len())
+                .map(|q| (0..len).map(|r| lce(r, q)).max().unwrap())
+                .collect::<Vec<_>>();
+
+            for step in [1, 3, 7] {
+                let idxs = (0..len).step_by(step).collect::<Vec<usize>>();
+                let ssa = Ssa::new(t, &idxs);
+                let k = ssa.sparseness(len);
+                let hasher = RollingHash::new_random(t, 8);
+                let ms = ssa.matching_statistics(&hasher, &query);
+                if step == 1 {
+                    assert_eq!(ms, naive);
+                }
+                for q in 0..query.len() {
+                    if naive[q] >= k {
+                        assert_eq!(ms[q], naive[q], "q {q} step {step}");
+                    }
+                    // At least the longest match starting at a sampled position is found.
+                    let sampled = idxs.iter().map(|&r| lce(r, q)).max().unwrap();
+                    assert!(sampled <= ms[q] && ms[q] <= naive[q], "q {q} step {step}");
+                    if q > 0 {
+                        assert!(ms[q] + 1 >= ms[q - 1], "q {q} step {step}");
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn random() {
+        for len in [10, 100, 1000, 3000] {
+            let t = &(0..len)
+                .map(|_| rand::random::<u8>() % 4)
+                .collect::<Vec<_>>();
+            // A mutated copy of part of the text, so that there are long MEMs.
+            let start = rand::random::<usize>() % len;
+            let mut query = t[start..(start + 300).min(len)].to_vec();
+            for _ in 0..query.len() / 20 {
+                let k = rand::random::<usize>() % query.len();
+                query[k] = rand::random::<u8>() % 4;
+            }
+            query.extend((0..50).map(|_| rand::random::<u8>() % 4));
+
+            for step in [1, 3, 7] {
+                let idxs = (0..len).step_by(step).collect::<Vec<usize>>();
+                let ssa = Ssa::new(t, &idxs);
+                let hasher = RollingHash::new_random(t, 8);
+                for min_len in [step, 10, 20] {
+                    let mems = ssa.mems(&hasher, &query, min_len);
+                    assert_eq!(mems, naive_mems(t, &idxs, &query, min_len));
+                }
+            }
+            // Random sampling, where some MEMs are not found.
+            let idxs = (0..len)
+                .filter(|_| rand::random::<f64>() < 0.3)
+                .collect::<Vec<usize>>();
+            if idxs.is_empty() {
+                continue;
+            }
+            let ssa = Ssa::new(t, &idxs);
+            let hasher = RollingHash::new(t, 8);
+            for min_len in [1, 5, 15] {
+                let mems = ssa.mems(&hasher, &query, min_len);
+                assert_eq!(mems, naive_mems(t, &idxs, &query, min_len));
+            }
+        }
+    }
+}
@@ -0,0 +1,168 @@
+//! Combining the sorted suffixes of different sample sets of the same text.
+use std::cmp::Ordering;
+
+use crate::{
+    builder::BuildStats,
+    rolling_hash::RollingHash,
+    search::{cmp_suffixes_hashed, lce_suffixes},
+    sparse_hasher_sampling, EndOfText, Idx, Ssa, Suffixes,
+};
+
+/// Interleave the SSAs `a` and `b`, where `b.sa[j]` goes before `a.sa[pos[j]]`.
+/// LCPs of pairs that were already adjacent are copied, and the others are computed using `hasher`.
+fn interleave<I: Idx>(a: &Ssa<I>, b: &Ssa<I>, pos: &[usize], hasher: &RollingHash) -> Ssa<I> {
+    let len = a.sa.len() + b.sa.len();
+    let mut sa = Vec::with_capacity(len);
+    let mut lcp = Vec::with_capacity(len - 1);
+    // The previous element, as `(from_b, rank)`.
+    let mut prev: Option<(bool, usize)> = None;
+    let mut push = |cur: (bool, usize)| {
+        let ssa = if cur.0 { b } else { a };
+        if let Some(prev) = prev {
+            lcp.push(if prev.0 == cur.0 && prev.1 + 1 == cur.1 {
+                ssa.lcp[prev.1]
+            } else {
+                let prev_idx = if prev.0 { b.sa[prev.1] } else { a.sa[prev.1] };
+                I::from_usize(lce_suffixes(
+                    hasher,
+                    prev_idx.as_usize(),
+                    ssa.sa[cur.1].as_usize(),
+                ))
+            });
+        }
+        sa.push(ssa.sa[cur.1]);
+        prev = Some(cur);
+    };
+    let mut j = 0;
+    for i in 0..=a.sa.len() {
+        while j < b.sa.len() && pos[j] == i {
+            push((true, j));
+            j += 1;
+        }
+        if i < a.sa.len() {
+            push((false, i));
+        }
+    }
+    Ssa { sa, lcp }
+}
+
+impl<I: Idx> Ssa<I> {
+    /// Add the suffixes starting at `new_idxs` to this SSA of `t`.
+    /// The new suffixes are sorted on their own, and then inserted by binary search
+    /// using hash-based LCE queries. Only the LCPs around inserted suffixes are recomputed.
+    /// Panics when a position is already sampled.
+    pub fn insert(&mut self, t: &[u8], new_idxs: &[I]) {
+        if new_idxs.is_empty() {
+            return;
+        }
+        let n = t.len();
+        let b = self.sa.len() + new_idxs.len();
+        let hasher = RollingHash::new(t, sparse_hasher_sampling(n, b));
+        let new = Self::new_with_hasher(
+            t,
+            Suffixes::Text(EndOfText::Smallest),
+            new_idxs,
+            Some(new_idxs.len().ilog2() as _),
+            true,
+            1,
+            &hasher,
+            &mut BuildStats::default(),
+        );
+
+        // The new suffixes are sorted, so each search continues where the previous one ended.
+        let mut pos = Vec::with_capacity(new.sa.len());
+        let mut lo = 0;
+        for &x in &new.sa {
+            lo += self.sa[lo..].partition_point(|&y| {
+                let (ord, _) = cmp_suffixes_hashed(&hasher, y.as_usize(), x.as_usize());
+                assert!(ord != Ordering::Equal, "Position {x:?} is already sampled.");
+                ord == Ordering::Less
+            });
+            pos.push(lo);
+        }
+        *self = interleave(self, &new, &pos, &hasher);
+    }
+
+    /// The SSA of the union of the disjoint sample sets of `a` and `b`, both built on `t`.
+    /// The sorted orders are merged in a single pass using hash-based LCE queries,
+    /// so that SSAs of parts of the sample set can be built independently.
+    /// Panics when a position is sampled in both.
+    pub fn merge(a: &Self, b: &Self, t: &[u8]) -> Self {
+        let n = t.len();
+        let len = a.sa.len() + b.sa.len();
+        let hasher = RollingHash::new(t, sparse_hasher_sampling(n, len));
+
+        let mut pos = Vec::with_capacity(b.sa.len());
+        let mut i = 0;
+        for &x in &b.sa {
+            while i < a.sa.len() {
+                let (ord, _) = cmp_suffixes_hashed(&hasher, a.sa[i].as_usize(), x.as_usize());
+                assert!(ord != Ordering::Equal, "Position {x:?} is sampled twice.");
+                if ord == Ordering::Greater {
+                    break;
+                }
+                i += 1;
+            }
+            pos.push(i);
+        }
+        interleave(a, b, &pos, &hasher)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn insert() {
+        for len in [1, 10, 100, 1000, 10000] {
+            let t = &(0..len)
+                .map(|_| rand::random::<u8>() % 4)
+                .collect::<Vec<_>>();
+            let (old, new): (Vec<usize>, Vec<usize>) =
+                (0..len).partition(|_| rand::random::<f64>() < 0.7);
+            if old.is_empty() {
+                continue;
+            }
+            let mut ssa = Ssa::new(t, &old);
+            ssa.insert(t, &new);
+            let expected = Ssa::new(t, &(0..len).collect::<Vec<_>>());
+            assert_eq!(ssa.sa, expected.sa);
+            assert_eq!(ssa.lcp, expected.lcp);
+        }
+        // A few positions into a repetitive text.
+        let t = &vec![0; 10000];
+        let mut ssa = Ssa::<u32>::new(t, &[0, 10, 5000]);
+        ssa.insert(t, &[9999, 3, 7]);
+        assert_eq!(ssa.sa, vec![9999, 5000, 10, 7, 3, 0]);
+        assert_eq!(ssa.lcp, vec![1, 5000, 9990, 9993, 9997]);
+    }
+
+    #[test]
+    fn merge() {
+        for len in [2, 10, 100, 1000, 10000] {
+            let t = &(0..len)
+                .map(|_| rand::random::<u8>() % 4)
+                .collect::<Vec<_>>();
+            let (a, b): (Vec<u32>, Vec<u32>) =
+                (0..len as u32).partition(|_| rand::random::<f64>() < 0.5);
+            if a.is_empty() || b.is_empty() {
+                continue;
+            }
+            let merged = Ssa::merge(&Ssa::new(t, &a), &Ssa::new(t, &b), t);
+            let expected = Ssa::new(t, &(0..len as u32).collect::<Vec<_>>());
+            assert_eq!(merged.sa, expected.sa);
+            assert_eq!(merged.lcp, expected.lcp);
+        }
+        // Partitioned construction of a repetitive text.
+        let t = &b"abc".repeat(1000);
+        let idxs = (0..t.len()).step_by(2).collect::<Vec<_>>();
+        let mut merged = Ssa::new(t, &idxs[..100]);
+        for chunk in idxs[100..].chunks(100) {
+            merged = Ssa::merge(&merged, &Ssa::new(t, chunk), t);
+        }
+        let expected = Ssa::new(t, &idxs);
+        assert_eq!(merged.sa, expected.sa);
+        assert_eq!(merged.lcp, expected.lcp);
+    }
+}
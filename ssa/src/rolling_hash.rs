@@ -1,8 +1,12 @@
 use std::ops::{Add, Mul, Range, Sub};
 
+use rand::Rng;
+
+use crate::EndOfText;
+
 // (2^64-15)/53
 // This has the property that 2^64 mod P = 15, and 15*P < 2^64.
-const P: u64 = 348051774975651917;
+pub(crate) const P: u64 = 348051774975651917;
 const R: u64 = 15;
 // (2^64-17)/(19*67)
 // This can be multiplied by 256 without overflow.
@@ -14,7 +18,7 @@ const R: u64 = 15;
 // const P: Hash = (1 << 61) - 1;
 const BASE: u64 = 256;
 
-#[derive(PartialEq, Eq, Debug, Clone, Copy, Default, PartialOrd, Ord)]
+#[derive(PartialEq, Eq, Hash, Debug, Clone, Copy, Default, PartialOrd, Ord)]
 pub struct Mod(pub u64);
 impl Mul<u64> for Mod {
     type Output = Self;
@@ -92,9 +96,11 @@ pub struct RollingHash<'a> {
     /// Store a prefix every s positions.
     s: usize,
     log_s: u32,
-    /// 256^s
+    /// The base of the polynomial hash. `BASE` uses fast 8-chars-at-a-time hashing.
+    base: u64,
+    /// base^s
     f: Mod,
-    /// 256^-1
+    /// base^-1
     base_inv: Mod,
     // TODO: Also store f^i values?
     prefixes: Vec<Mod>,
@@ -108,11 +114,32 @@ impl<'a> RollingHash<'a> {
     /// Note that `s` must be a power of 2 at least 8 for efficiency.
     /// Answers queries in time `O(min(|t|, |n/s|))`.
     pub fn new(text: &'a [u8], s: usize) -> Self {
+        Self::with_base(text, s, BASE)
+    }
+
+    /// Like `new`, but with a uniformly random base, so that collisions do not depend on the input.
+    /// Hashing is slower than with the default base, since it is done one char at a time.
+    pub fn new_random(text: &'a [u8], s: usize) -> Self {
+        Self::with_base(text, s, rand::thread_rng().gen_range(BASE + 1..P))
+    }
+
+    /// A hasher over `text` with the same base as `self`, so that their hashes can be compared.
+    pub fn new_same_base<'b>(&self, text: &'b [u8], s: usize) -> RollingHash<'b> {
+        RollingHash::with_base(text, s, self.base)
+    }
+
+    /// The hashed text.
+    pub fn text(&self) -> &'a [u8] {
+        self.text
+    }
+
+    fn with_base(text: &'a [u8], s: usize, base: u64) -> Self {
         let n = text.len();
         assert!(s % 8 == 0);
         assert!(s.is_power_of_two());
-        // p^s
-        let f = Mod(R).pow((s / 8) as u64);
+        assert!(base < P);
+        // base^s
+        let f = Mod(base).pow(s as u64);
 
         let mut prefixes = Vec::with_capacity(n / s);
 
@@ -120,7 +147,7 @@ impl<'a> RollingHash<'a> {
         let mut f_acc = Mod(1);
         prefixes.push(prefix);
         for t in text.chunks_exact(s) {
-            let h = Self::linear(t);
+            let h = Self::linear_with_base(t, base);
             prefix = h.mul_add(f_acc.0, prefix);
             prefixes.push(prefix);
             f_acc = f_acc * f;
@@ -130,8 +157,9 @@ impl<'a> RollingHash<'a> {
             text,
             s,
             log_s: s.ilog2(),
+            base,
             f,
-            base_inv: Mod(BASE).pow(P - 2),
+            base_inv: Mod(base).pow(P - 2),
             prefixes,
         }
     }
@@ -174,21 +202,118 @@ impl<'a> RollingHash<'a> {
         assert!(range.start <= self.text.len());
         if range.end > self.text.len() {
             assert!(range.end <= 2 * self.text.len());
-            return Self::offset(Mod(0u64.wrapping_sub((range.end - self.text.len()) as u64)));
+            return Self::sentinel(range.end - self.text.len(), EndOfText::Smallest);
         }
+        Self::offset(self.query_raw(range))
+    }
+
+    /// Like `query`, with ranges extending beyond the text ordered according to `end`.
+    /// For `EndOfText::Largest`, they return `Mod(OFFSET+P+overshoot)`, which is larger
+    /// than any hash.
+    pub fn query_end(&self, range: Range<usize>, end: EndOfText) -> Mod {
+        match end {
+            EndOfText::Largest if range.end > self.text.len() => {
+                Self::sentinel(range.end - self.text.len(), end)
+            }
+            _ => self.query(range),
+        }
+    }
+
+    /// Like `query_end`, for the `range` of offsets into the reverse of the prefix ending at `end`,
+    /// i.e. into the suffix of the reverse text starting at `n-end`.
+    /// Ranges extending beyond the start of the text are ordered according to `eot`.
+    /// This returns the hash of the corresponding forward range, so it can only be compared to other reverse queries.
+    pub fn query_rev(&self, end: usize, range: Range<usize>, eot: EndOfText) -> Mod {
+        assert!(end <= self.text.len());
+        if range.end > end {
+            return Self::sentinel(range.end - end, eot);
+        }
+        self.query(end - range.end..end - range.start)
+    }
+
+    /// The hash of a range extending `overshoot` characters beyond the text.
+    fn sentinel(overshoot: usize, end: EndOfText) -> Mod {
+        match end {
+            EndOfText::Smallest => Self::offset(Mod(0u64.wrapping_sub(overshoot as u64))),
+            EndOfText::Largest => Self::offset(Mod(P + overshoot as u64)),
+        }
+    }
+
+    /// The hash of a range inside the text, without offset.
+    fn query_raw(&self, range: Range<usize>) -> Mod {
         let Range { start: i, end: j } = range;
         if range.len() <= 2 * self.s {
-            return Self::offset(Self::linear(&self.text[range]));
+            return Self::linear_with_base(&self.text[range], self.base);
         }
         let l = i >> self.log_s;
         let r = j >> self.log_s;
         let pl = self.prefixes[l];
         let pr = self.prefixes[r];
-        let sl = Self::linear(&self.text[(l << self.log_s)..i]);
-        let sr = Self::linear(&self.text[(r << self.log_s)..j]);
+        let sl = Self::linear_with_base(&self.text[(l << self.log_s)..i], self.base);
+        let sr = Self::linear_with_base(&self.text[(r << self.log_s)..j], self.base);
         let hl = pl + self.f.pow(l as u64) * sl;
         let hr = pr + self.f.pow(r as u64) * sr;
-        Self::offset((hr - hl) * self.base_inv.pow(i as u64))
+        (hr - hl) * self.base_inv.pow(i as u64)
+    }
+
+    /// Like `query`, for a range inside the `record`th of `records` consecutive texts, which ends at `end`.
+    /// Ranges extending beyond `end` return `Mod(OFFSET-(overshoot*records+records-1-record))`,
+    /// so that shorter suffixes sort first, and equal suffixes sort by record.
+    /// For a single record this is identical to `query`.
+    pub fn query_record(
+        &self,
+        range: Range<usize>,
+        end: usize,
+        record: usize,
+        records: usize,
+    ) -> Mod {
+        if range.end > end {
+            let overshoot = range.end - end;
+            let x = overshoot * records + records - 1 - record;
+            return Self::offset(Mod(0u64.wrapping_sub(x as u64)));
+        }
+        self.query(range)
+    }
+
+    /// Like `query`, for a range of the infinitely repeated text, as a substring of the rotation
+    /// starting at `start < n`. Ranges may wrap around the end of the text.
+    /// Ranges extending beyond `start+n` return `Mod(OFFSET-(overshoot*n+n-1-start))`,
+    /// so that equal rotations of a periodic text sort by start position.
+    pub fn query_circular(&self, start: usize, range: Range<usize>) -> Mod {
+        let n = self.text.len();
+        assert!(start < n && start <= range.start);
+        if range.end > start + n {
+            return Self::circular_sentinel(range.end - start - n, n, start);
+        }
+        let i = range.start % n;
+        let len = range.len();
+        if i + len <= n {
+            return self.query(i..i + len);
+        }
+        let head = self.query_raw(i..n);
+        let tail = self.query_raw(0..i + len - n);
+        Self::offset(head + Mod(self.base).pow((n - i) as u64) * tail)
+    }
+
+    /// Like `query_circular`, for the `range` of offsets into the reverse of the rotation ending just before `end < n`,
+    /// i.e. into the rotation of the reverse text starting at `(n-end) % n`.
+    /// Ranges extending beyond `n` are ordered by this start position.
+    /// As for `query_rev`, this can only be compared to other reverse queries.
+    pub fn query_circular_rev(&self, end: usize, range: Range<usize>) -> Mod {
+        let n = self.text.len();
+        assert!(end < n);
+        if range.end > n {
+            return Self::circular_sentinel(range.end - n, n, (n - end) % n);
+        }
+        let i = (end + n - range.end) % n;
+        self.query_circular(i, i..i + range.len())
+    }
+
+    /// The hash of a range extending `overshoot` characters beyond the rotation starting at `start`,
+    /// `Mod(OFFSET-(overshoot*n+n-1-start))`.
+    fn circular_sentinel(overshoot: usize, n: usize, start: usize) -> Mod {
+        let x = overshoot * n + n - 1 - start;
+        Self::offset(Mod(0u64.wrapping_sub(x as u64)))
     }
 
     /// Hash `t` 8 chars at a time.
@@ -208,9 +333,21 @@ impl<'a> RollingHash<'a> {
         h
     }
 
+    /// Hash `t` using the given base. Only `BASE` uses the fast chunked implementation.
+    fn linear_with_base(t: &[u8], base: u64) -> Mod {
+        if base == BASE {
+            return Self::linear(t);
+        }
+        let mut h = Mod(0);
+        for &c in t.iter().rev() {
+            h = h.mul_add(base, Mod(c as u64));
+        }
+        h
+    }
+
     #[cfg(test)]
     pub fn query_linear(&self, range: Range<usize>) -> Mod {
-        Self::offset(Self::linear(&self.text[range]))
+        Self::offset(Self::linear_with_base(&self.text[range], self.base))
     }
 
     /// Hash `t` one char at a time.
@@ -273,6 +410,116 @@ mod test {
         }
     }
 
+    #[test]
+    fn random_rolling_hash() {
+        for s in [8, 16, 64, 1024] {
+            for len in (0..100).chain([100, 1000, 10000].iter().cloned()) {
+                let t = (0..len).map(|_| rand::random::<u8>()).collect::<Vec<_>>();
+                let rolling_hash = RollingHash::new_random(&t, s);
+                for _ in 0..100 {
+                    let mut i = rand::thread_rng().gen_range(0..=len);
+                    let mut j = rand::thread_rng().gen_range(0..=len);
+                    if j < i {
+                        (i, j) = (j, i);
+                    }
+                    let h1 = rolling_hash.query(i..j);
+                    let h2 = rolling_hash.query_linear(i..j);
+                    assert_eq!(h1, h2, "Hash mismatch for len {len}, s {s}, i {i}, j {j}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn circular() {
+        for s in [8, 16, 64] {
+            for len in [1, 2, 10, 100, 1000] {
+                let t = (0..len).map(|_| rand::random::<u8>()).collect::<Vec<_>>();
+                let tt = t.repeat(2);
+                let rolling_hash = RollingHash::new_random(&t, s);
+                let doubled = rolling_hash.new_same_base(&tt, s);
+                for _ in 0..100 {
+                    let start = rand::thread_rng().gen_range(0..len);
+                    let mut i = rand::thread_rng().gen_range(0..=len);
+                    let mut j = rand::thread_rng().gen_range(0..=len);
+                    if j < i {
+                        (i, j) = (j, i);
+                    }
+                    let h1 = rolling_hash.query_circular(start, start + i..start + j);
+                    let h2 = doubled.query_linear(start + i..start + j);
+                    assert_eq!(h1, h2, "Hash mismatch for len {len}, s {s}, start {start}");
+                }
+                // Equal rotations are distinguished beyond the length of the text.
+                if len > 1 {
+                    let h0 = rolling_hash.query_circular(0, len..len + 1);
+                    let h1 = rolling_hash.query_circular(1, len + 1..len + 2);
+                    assert!(h0 < h1);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn reverse() {
+        for len in [1, 2, 10, 100, 1000] {
+            let t = (0..len)
+                .map(|_| rand::random::<u8>() % 4)
+                .collect::<Vec<_>>();
+            let rev = t.iter().rev().copied().collect::<Vec<_>>();
+            let rolling_hash = RollingHash::new(&t, 8);
+            let reversed = RollingHash::new(&rev, 8);
+            for _ in 0..100 {
+                let end = rand::thread_rng().gen_range(0..=len);
+                let end2 = rand::thread_rng().gen_range(0..=len);
+                let i = rand::thread_rng().gen_range(0..=end.min(end2));
+                let j = rand::thread_rng().gen_range(i..=len);
+                // Equal hashes exactly for equal substrings of the reverse text.
+                let h1 = rolling_hash.query_rev(end, i..j, EndOfText::Smallest);
+                let h2 = rolling_hash.query_rev(end2, i..j, EndOfText::Smallest);
+                let r1 = reversed.query(len - end + i..len - end + j);
+                let r2 = reversed.query(len - end2 + i..len - end2 + j);
+                assert_eq!(h1 == h2, r1 == r2, "len {len}, ends {end} {end2}, {i}..{j}");
+                // Ranges beyond the start are ordered like beyond the end.
+                if j > end {
+                    assert_eq!(h1, r1);
+                }
+            }
+            // Single characters are ordered.
+            let h = |end| rolling_hash.query_rev(end, 0..1, EndOfText::Largest);
+            for end in 1..len {
+                assert_eq!(h(end) < h(end + 1), t[end - 1] < t[end]);
+            }
+            assert!(h(0) > h(1));
+        }
+    }
+
+    #[test]
+    fn circular_reverse() {
+        for len in [1, 2, 10, 100] {
+            let t = (0..len)
+                .map(|_| rand::random::<u8>() % 4)
+                .collect::<Vec<_>>();
+            let rev = t.iter().rev().copied().collect::<Vec<_>>();
+            let rolling_hash = RollingHash::new(&t, 8);
+            let reversed = RollingHash::new(&rev, 8);
+            for _ in 0..100 {
+                let end = rand::thread_rng().gen_range(0..len);
+                let end2 = rand::thread_rng().gen_range(0..len);
+                let i = rand::thread_rng().gen_range(0..=len + 1);
+                let j = rand::thread_rng().gen_range(i..=len + 1);
+                let (s1, s2) = ((len - end) % len, (len - end2) % len);
+                let h1 = rolling_hash.query_circular_rev(end, i..j);
+                let h2 = rolling_hash.query_circular_rev(end2, i..j);
+                let r1 = reversed.query_circular(s1, s1 + i..s1 + j);
+                let r2 = reversed.query_circular(s2, s2 + i..s2 + j);
+                assert_eq!(h1 == h2, r1 == r2, "len {len}, ends {end} {end2}, {i}..{j}");
+                if j > len {
+                    assert_eq!(h1, r1);
+                }
+            }
+        }
+    }
+
     #[test]
     fn bench_linear_simple() {
         let mut sum = Wrapping(0);
@@ -0,0 +1,160 @@
+use std::{cmp::Ordering, ops::Range};
+
+use crate::{rolling_hash::RollingHash, Idx, Ssa};
+
+/// Longest common extension of the suffixes of `text` starting at `a` and `b`.
+/// Uses exponential search, so that it takes `O(log lce)` hash comparisons.
+pub(crate) fn lce_suffixes(text: &RollingHash, a: usize, b: usize) -> usize {
+    lce_texts(text, a, text, b)
+}
+
+/// Longest common extension of the suffix of `text` starting at `a` and the suffix of `pattern` starting at `b`.
+/// `pattern` must use the same base as `text`. Uses exponential search, like `lce_suffixes`.
+pub(crate) fn lce_texts(text: &RollingHash, a: usize, pattern: &RollingHash, b: usize) -> usize {
+    let max = (text.text().len() - a).min(pattern.text().len() - b);
+    let eq = |l: usize| text.query(a..a + l) == pattern.query(b..b + l);
+    // Invariant: the LCE is in `lo..=hi`.
+    let mut lo = 0;
+    let mut hi = 1.min(max);
+    while hi < max && eq(hi) {
+        lo = hi;
+        hi = (2 * hi).min(max);
+    }
+    while lo < hi {
+        let mid = (lo + hi).div_ceil(2);
+        if eq(mid) {
+            lo = mid;
+        } else {
+            hi = mid - 1;
+        }
+    }
+    lo
+}
+
+/// Compare the suffixes of `text` starting at `a` and `b` using hashes, where a suffix sorts before its extensions.
+/// Also returns their LCE. See `crate::cmp_suffixes` for the naive comparison with a configurable end.
+pub(crate) fn cmp_suffixes_hashed(text: &RollingHash, a: usize, b: usize) -> (Ordering, usize) {
+    let l = lce_suffixes(text, a, b);
+    let t = text.text();
+    let ord = match (t.get(a + l), t.get(b + l)) {
+        (Some(x), Some(y)) => x.cmp(y),
+        (x, y) => x.is_some().cmp(&y.is_some()),
+    };
+    (ord, l)
+}
+
+/// Compare the suffix starting at `a` to `pattern[range]`.
+/// Suffixes starting with the pattern compare `Equal`.
+pub(crate) fn cmp_suffix(
+    text: &RollingHash,
+    a: usize,
+    pattern: &RollingHash,
+    range: Range<usize>,
+) -> Ordering {
+    let l = lce_texts(text, a, pattern, range.start).min(range.len());
+    let (t, p) = (text.text(), pattern.text());
+    if l == range.len() {
+        Ordering::Equal
+    } else if a + l == t.len() {
+        Ordering::Less
+    } else {
+        t[a + l].cmp(&p[range.start + l])
+    }
+}
+
+/// The interval of `sa` containing exactly the suffixes starting with `pattern[range]`.
+/// `pattern` must use the same base as `text`.
+pub(crate) fn find_range<I: Idx>(
+    sa: &[I],
+    text: &RollingHash,
+    pattern: &RollingHash,
+    range: Range<usize>,
+) -> Range<usize> {
+    let cmp = |&a: &I| cmp_suffix(text, a.as_usize(), pattern, range.clone());
+    let start = sa.partition_point(|a| cmp(a) == Ordering::Less);
+    let end = start + sa[start..].partition_point(|a| cmp(a) == Ordering::Equal);
+    start..end
+}
+
+/// The interval of `sa` containing exactly the suffixes starting with `pattern`.
+pub(crate) fn find<I: Idx>(sa: &[I], text: &RollingHash, pattern: &[u8]) -> Range<usize> {
+    let pattern = text.new_same_base(pattern, 8);
+    find_range(sa, text, &pattern, 0..pattern.text().len())
+}
+
+impl<I: Idx> Ssa<I> {
+    /// The interval of the SA containing exactly the sampled suffixes starting with `pattern`.
+    /// `text` must be a hasher over the text this SSA was built on.
+    /// Suffixes are compared to the pattern using `O(log |pattern|)` hash comparisons.
+    /// A small sampling for `text`, e.g. 8, makes these hash queries fast.
+    pub fn find(&self, text: &RollingHash, pattern: &[u8]) -> Range<usize> {
+        find(&self.sa, text, pattern)
+    }
+
+    /// The number of sampled suffixes starting with `pattern`.
+    pub fn count(&self, text: &RollingHash, pattern: &[u8]) -> usize {
+        self.find(text, pattern).len()
+    }
+
+    /// The positions of the sampled suffixes starting with `pattern`, in SA order.
+    pub fn locate(&self, text: &RollingHash, pattern: &[u8]) -> &[I] {
+        &self.sa[self.find(text, pattern)]
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn small() {
+        let t = b"abracadabra";
+        let idxs = (0..t.len()).collect::<Vec<_>>();
+        let ssa = Ssa::new(t, &idxs);
+        let hasher = RollingHash::new(t, 8);
+        let mut locs = ssa.locate(&hasher, b"abra").to_vec();
+        locs.sort();
+        assert_eq!(locs, vec![0, 7]);
+        assert_eq!(ssa.count(&hasher, b"a"), 5);
+        assert_eq!(ssa.count(&hasher, b"abracadabra"), 1);
+        assert_eq!(ssa.count(&hasher, b"abracadabrab"), 0);
+        assert_eq!(ssa.count(&hasher, b"ra"), 2);
+        assert_eq!(ssa.count(&hasher, b"rab"), 0);
+        assert_eq!(ssa.count(&hasher, b""), t.len());
+    }
+
+    #[test]
+    fn random() {
+        for len in [10, 100, 1000, 10000] {
+            let t = &(0..len)
+                .map(|_| rand::random::<u8>() % 4)
+                .collect::<Vec<_>>();
+            let idxs = (0..len)
+                .filter(|_| rand::random::<f64>() < 0.3)
+                .collect::<Vec<usize>>();
+            if idxs.is_empty() {
+                continue;
+            }
+            let ssa = Ssa::new(t, &idxs);
+            let hasher = RollingHash::new_random(t, 8);
+            for _ in 0..100 {
+                let m = rand::random::<usize>() % 12;
+                let i = rand::random::<usize>() % len;
+                // Half the patterns occur in the text.
+                let pattern = if rand::random() {
+                    t[i..(i + m).min(len)].to_vec()
+                } else {
+                    (0..m).map(|_| rand::random::<u8>() % 4).collect()
+                };
+                let mut locs = ssa.locate(&hasher, &pattern).to_vec();
+                locs.sort();
+                let expected = idxs
+                    .iter()
+                    .copied()
+                    .filter(|&i| t[i..].starts_with(&pattern))
+                    .collect::<Vec<_>>();
+                assert_eq!(locs, expected);
+            }
+        }
+    }
+}
@@ -0,0 +1,111 @@
+//! Building an `Ssa` for suffixes that are already sorted, e.g. by another tool.
+use std::cmp::Ordering;
+
+use crate::{
+    builder::SsaError,
+    rolling_hash::RollingHash,
+    search::cmp_suffixes_hashed,
+    sparse_hasher_sampling,
+    verify::{Failure, FailureKind},
+    Idx, Ssa,
+};
+
+impl<I: Idx> Ssa<I> {
+    /// Compute the LCP array for the suffixes of `t` in the given order, without checking it.
+    /// Positions may include the empty suffix at `t.len()`.
+    /// Each LCP takes `O(log lcp)` hash comparisons.
+    /// Panics when a position is out of range.
+    pub fn from_sorted(t: &[u8], sa: Vec<I>) -> Self {
+        from_sorted(t, sa, false).unwrap_or_else(|e| panic!("{e}"))
+    }
+
+    /// Like `from_sorted`, but also checks that the suffixes are in increasing order,
+    /// where a suffix sorts before its extensions.
+    /// Returns `SsaError::NotSorted` with the first pair that is out of order.
+    pub fn from_sorted_checked(t: &[u8], sa: Vec<I>) -> Result<Self, SsaError> {
+        from_sorted(t, sa, true)
+    }
+}
+
+fn from_sorted<I: Idx>(t: &[u8], sa: Vec<I>, check: bool) -> Result<Ssa<I>, SsaError> {
+    let n = t.len();
+    // As in `SsaBuilder`, the empty suffix at `n` is allowed, but only for a non-empty text.
+    if let Some(idx) = sa.iter().find(|&&i| i.as_usize() > n || n == 0) {
+        return Err(SsaError::OutOfRange {
+            idx: idx.as_usize(),
+            n,
+        });
+    }
+    let hasher = RollingHash::new(t, sparse_hasher_sampling(n, sa.len()));
+    let mut lcp = Vec::with_capacity(sa.len().saturating_sub(1));
+    for (i, w) in sa.windows(2).enumerate() {
+        let (a, c) = (w[0].as_usize(), w[1].as_usize());
+        let (ord, l) = cmp_suffixes_hashed(&hasher, a, c);
+        if check && ord != Ordering::Less {
+            return Err(SsaError::NotSorted(Failure {
+                i,
+                kind: FailureKind::Order,
+                sa: (a, c),
+                lcp: l,
+            }));
+        }
+        lcp.push(I::from_usize(l));
+    }
+    Ok(Ssa { sa, lcp })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn from_sorted() {
+        for len in [1, 10, 100, 1000, 10000] {
+            let t = &(0..len)
+                .map(|_| rand::random::<u8>() % 4)
+                .collect::<Vec<_>>();
+            let idxs = (0..len)
+                .filter(|_| rand::random::<f64>() < 0.3)
+                .collect::<Vec<usize>>();
+            if idxs.is_empty() {
+                continue;
+            }
+            let ssa = Ssa::new(t, &idxs);
+            let ssa2 = Ssa::from_sorted(t, ssa.sa.clone());
+            assert_eq!(ssa.lcp, ssa2.lcp);
+            let ssa3 = Ssa::from_sorted_checked(t, ssa.sa.clone()).unwrap();
+            assert_eq!(ssa.lcp, ssa3.lcp);
+
+            if idxs.len() > 1 {
+                let mut sa = ssa.sa.clone();
+                let i = rand::random::<usize>() % (sa.len() - 1);
+                sa.swap(i, i + 1);
+                let Some(SsaError::NotSorted(failure)) = Ssa::from_sorted_checked(t, sa).err()
+                else {
+                    panic!("expected an order failure");
+                };
+                assert_eq!(failure.i, i);
+                assert_eq!(failure.kind, FailureKind::Order);
+                assert_eq!(failure.lcp, ssa.lcp[i]);
+            }
+        }
+        // Repetitive input with long LCPs.
+        let t = &b"ACGT".repeat(5000);
+        let idxs = (0..t.len()).step_by(7).collect::<Vec<_>>();
+        let ssa = Ssa::new(t, &idxs);
+        assert_eq!(ssa.lcp, Ssa::from_sorted(t, ssa.sa.clone()).lcp);
+    }
+
+    #[test]
+    fn empty_suffix() {
+        let t = b"abracadabra";
+        let ssa = Ssa::new(t, &[0, 3, 7, t.len()]);
+        assert_eq!(ssa.sa[0], t.len());
+        let ssa2 = Ssa::from_sorted_checked(t, ssa.sa.clone()).unwrap();
+        assert_eq!(ssa.lcp, ssa2.lcp);
+        assert_eq!(
+            Ssa::from_sorted_checked(t, vec![0, t.len() + 1]).err(),
+            Some(SsaError::OutOfRange { idx: 12, n: 11 })
+        );
+    }
+}
@@ -0,0 +1,302 @@
+use std::ops::Range;
+
+use crate::{Idx, Ssa};
+
+/// A sparse suffix tree, with an internal node for each LCP interval of an `Ssa`.
+/// Nodes `0..b` are the leaves, in SA order. Internal nodes follow in post-order.
+/// Since there are no end-of-text markers, a leaf can have the same depth as its parent.
+/// The root has depth 0. When all sampled suffixes share a prefix, it has a single child.
+pub struct SuffixTree<I = usize> {
+    /// The number of leaves.
+    b: usize,
+    root: usize,
+    /// String depth of each node.
+    depth: Vec<I>,
+    /// Parent of each node, or `I::MAX` for the root.
+    parent: Vec<I>,
+    /// Range of SA ranks of the leaves below each node.
+    leaves: Vec<(I, I)>,
+    /// The children of internal node `b+v` are `children[child_start[v]..child_start[v+1]]`.
+    child_start: Vec<I>,
+    children: Vec<I>,
+}
+
+/// An LCP interval whose right end has not been found yet.
+struct Open {
+    depth: usize,
+    lb: usize,
+    children: Vec<usize>,
+}
+
+impl<I: Idx> SuffixTree<I> {
+    /// Build the tree bottom-up from the LCP array.
+    /// `n` is the length of the text the SSA was built on.
+    pub fn new(ssa: &Ssa<I>, n: usize) -> Self {
+        let b = ssa.sa.len();
+        let mut tree = Self {
+            b,
+            root: 0,
+            depth: ssa
+                .sa
+                .iter()
+                .map(|&i| I::from_usize(n - i.as_usize()))
+                .collect(),
+            parent: vec![I::MAX; b],
+            leaves: (0..b)
+                .map(|i| (I::from_usize(i), I::from_usize(i + 1)))
+                .collect(),
+            child_start: vec![I::default()],
+            children: vec![],
+        };
+
+        let mut stack: Vec<Open> = vec![];
+        for i in 0..b {
+            let mut pending = i;
+            let mut lb = i;
+            // The LCP with the next suffix, or `None` to close all intervals.
+            let l = ssa.lcp.get(i).map(|l| l.as_usize());
+            // Close intervals deeper than `l`.
+            while let Some(top) = stack.last_mut() {
+                if l.is_some_and(|l| l >= top.depth) {
+                    break;
+                }
+                top.children.push(pending);
+                let top = stack.pop().unwrap();
+                lb = top.lb;
+                pending = tree.push_node(top, i + 1);
+            }
+            let Some(l) = l else {
+                // Add a root of depth 0 above a common prefix.
+                tree.root = if tree.depth(pending) > 0 {
+                    let root = Open {
+                        depth: 0,
+                        lb,
+                        children: vec![pending],
+                    };
+                    tree.push_node(root, b)
+                } else {
+                    pending
+                };
+                break;
+            };
+            match stack.last_mut() {
+                Some(top) if top.depth == l => top.children.push(pending),
+                _ => stack.push(Open {
+                    depth: l,
+                    lb,
+                    children: vec![pending],
+                }),
+            }
+        }
+        tree
+    }
+
+    fn push_node(&mut self, open: Open, rb: usize) -> usize {
+        let v = self.depth.len();
+        self.depth.push(I::from_usize(open.depth));
+        self.parent.push(I::MAX);
+        self.leaves
+            .push((I::from_usize(open.lb), I::from_usize(rb)));
+        for &c in &open.children {
+            self.parent[c] = I::from_usize(v);
+        }
+        self.children
+            .extend(open.children.into_iter().map(I::from_usize));
+        self.child_start.push(I::from_usize(self.children.len()));
+        v
+    }
+
+    pub fn root(&self) -> usize {
+        self.root
+    }
+    /// The total number of nodes.
+    pub fn len(&self) -> usize {
+        self.depth.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.depth.is_empty()
+    }
+    pub fn num_leaves(&self) -> usize {
+        self.b
+    }
+    pub fn is_leaf(&self, v: usize) -> bool {
+        v < self.b
+    }
+    /// The string depth of `v`. For a leaf, this is the length of its suffix.
+    pub fn depth(&self, v: usize) -> usize {
+        self.depth[v].as_usize()
+    }
+    pub fn parent(&self, v: usize) -> Option<usize> {
+        let p = self.parent[v];
+        (p != I::MAX).then(|| p.as_usize())
+    }
+    /// The children of `v`, in lexicographic order.
+    pub fn children(&self, v: usize) -> impl Iterator<Item = usize> + '_ {
+        let range = if self.is_leaf(v) {
+            0..0
+        } else {
+            self.child_start[v - self.b].as_usize()..self.child_start[v - self.b + 1].as_usize()
+        };
+        self.children[range].iter().map(|c| c.as_usize())
+    }
+    /// The SA ranks of the leaves below `v`.
+    pub fn leaves(&self, v: usize) -> Range<usize> {
+        let (lb, rb) = self.leaves[v];
+        lb.as_usize()..rb.as_usize()
+    }
+
+    /// All nodes, parents before children, in lexicographic (pre-)order.
+    pub fn top_down(&self) -> impl Iterator<Item = usize> + '_ {
+        let mut stack = vec![self.root];
+        std::iter::from_fn(move || {
+            let v = stack.pop()?;
+            let len = stack.len();
+            stack.extend(self.children(v));
+            stack[len..].reverse();
+            Some(v)
+        })
+    }
+
+    /// All nodes, children before parents, in lexicographic (post-)order.
+    /// This is the order in which the LCP intervals are closed.
+    pub fn bottom_up(&self) -> impl Iterator<Item = usize> + '_ {
+        // Nodes with the index of the next child to visit.
+        let mut stack = vec![(self.root, 0)];
+        std::iter::from_fn(move || loop {
+            let (v, i) = stack.last_mut()?;
+            let v = *v;
+            match self.children(v).nth(*i) {
+                Some(c) => {
+                    *i += 1;
+                    stack.push((c, 0));
+                }
+                None => {
+                    stack.pop();
+                    return Some(v);
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn abracadabra() {
+        let t = b"abracadabra";
+        let idxs = (0..t.len()).collect::<Vec<_>>();
+        let ssa = Ssa::new(t, &idxs);
+        let tree = SuffixTree::new(&ssa, t.len());
+        // Internal nodes: root, a, abra, bra, ra.
+        assert_eq!(tree.len(), t.len() + 5);
+        let root = tree.root();
+        assert_eq!(tree.depth(root), 0);
+        assert_eq!(tree.leaves(root), 0..t.len());
+        assert_eq!(tree.parent(root), None);
+        let depths = tree
+            .children(root)
+            .map(|v| tree.depth(v))
+            .collect::<Vec<_>>();
+        // a, bra, cadabra, dabra, ra
+        assert_eq!(depths, vec![1, 3, 7, 5, 2]);
+        let a = tree.children(root).next().unwrap();
+        assert_eq!(tree.leaves(a), 0..5);
+        assert_eq!(tree.children(a).count(), 4);
+    }
+
+    #[test]
+    fn homopolymer() {
+        let t = b"aaaa";
+        let ssa = Ssa::new(t, &[0usize, 1, 2, 3]);
+        let tree = SuffixTree::new(&ssa, t.len());
+        // Leaves, nodes of depth 3, 2 and 1, and the root.
+        assert_eq!(tree.len(), t.len() + 4);
+        let root = tree.root();
+        assert_eq!(tree.depth(root), 0);
+        assert_eq!(tree.leaves(root), 0..t.len());
+        assert_eq!(tree.parent(root), None);
+        let a = tree.children(root).collect::<Vec<_>>();
+        assert_eq!(a.len(), 1);
+        assert_eq!(tree.depth(a[0]), 1);
+        assert_eq!(tree.parent(a[0]), Some(root));
+        assert_eq!(tree.top_down().next(), Some(root));
+        assert_eq!(tree.bottom_up().last(), Some(root));
+    }
+
+    #[test]
+    fn random() {
+        for &fraction in [0.1, 0.5, 1.0].iter() {
+            for len in [1, 2, 10, 100, 1000] {
+                let t = &(0..len)
+                    .map(|_| rand::random::<u8>() % 3)
+                    .collect::<Vec<_>>();
+                let idxs = (0..len)
+                    .filter(|_| rand::random::<f64>() < fraction)
+                    .collect::<Vec<usize>>();
+                if idxs.is_empty() {
+                    continue;
+                }
+                let ssa = Ssa::new(t, &idxs);
+                let tree = SuffixTree::new(&ssa, len);
+                assert_eq!(tree.num_leaves(), idxs.len());
+                assert_eq!(tree.leaves(tree.root()), 0..idxs.len());
+
+                for v in 0..tree.len() {
+                    let leaves = tree.leaves(v);
+                    if tree.is_leaf(v) {
+                        assert_eq!(tree.depth(v), len - ssa.sa[v]);
+                        continue;
+                    }
+                    // The depth is the minimal LCP in the interval, or 0 for the root.
+                    let lcps = &ssa.lcp[leaves.start..leaves.end - 1];
+                    if v == tree.root() {
+                        assert_eq!(tree.depth(v), 0);
+                    } else {
+                        assert_eq!(tree.depth(v), *lcps.iter().min().unwrap());
+                    }
+                    // Children partition the leaves, and are strictly deeper if internal.
+                    let mut start = leaves.start;
+                    for c in tree.children(v) {
+                        assert_eq!(tree.parent(c), Some(v));
+                        assert_eq!(tree.leaves(c).start, start);
+                        start = tree.leaves(c).end;
+                        if !tree.is_leaf(c) {
+                            assert!(tree.depth(c) > tree.depth(v));
+                        }
+                    }
+                    assert_eq!(start, leaves.end);
+                    assert!(tree.children(v).count() >= 2 || v == tree.root());
+                }
+
+                let top_down = tree.top_down().collect::<Vec<_>>();
+                let bottom_up = tree.bottom_up().collect::<Vec<_>>();
+                assert_eq!(top_down.len(), tree.len());
+                assert_eq!(bottom_up.len(), tree.len());
+                let mut pos = vec![(0, 0); tree.len()];
+                for (i, &v) in top_down.iter().enumerate() {
+                    pos[v].0 = i;
+                }
+                for (i, &v) in bottom_up.iter().enumerate() {
+                    pos[v].1 = i;
+                }
+                for v in 0..tree.len() {
+                    if let Some(p) = tree.parent(v) {
+                        assert!(pos[p].0 < pos[v].0);
+                        assert!(pos[p].1 > pos[v].1);
+                    }
+                }
+                // Leaves are visited in SA order.
+                let leaves = |order: Vec<usize>| {
+                    order
+                        .into_iter()
+                        .filter(|&v| tree.is_leaf(v))
+                        .collect::<Vec<_>>()
+                };
+                assert_eq!(leaves(top_down), (0..idxs.len()).collect::<Vec<_>>());
+                assert_eq!(leaves(bottom_up), (0..idxs.len()).collect::<Vec<_>>());
+            }
+        }
+    }
+}
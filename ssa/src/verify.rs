@@ -0,0 +1,192 @@
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+
+use crate::{rolling_hash::RollingHash, sparse_hasher_sampling, EndOfText, Idx, Ssa};
+
+/// At most this many failures are stored in a `Report`.
+pub const MAX_REPORTED: usize = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FailureKind {
+    /// The LCP-prefixes of the two suffixes differ, or one of them is shorter than the LCP.
+    LcpTooLong,
+    /// The characters following the LCP are equal.
+    LcpTooShort,
+    /// The suffixes are not in increasing order.
+    Order,
+}
+
+/// A failure between the suffixes at ranks `i` and `i+1`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Failure {
+    pub i: usize,
+    pub kind: FailureKind,
+    /// Positions of the two suffixes.
+    pub sa: (usize, usize),
+    /// The LCP stored for the pair.
+    pub lcp: usize,
+}
+
+/// Result of `Ssa::verify_hashed`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Report {
+    /// The number of checked adjacent pairs.
+    pub checked: usize,
+    /// The total number of failures.
+    pub num_failures: usize,
+    /// The first `MAX_REPORTED` failures, by rank.
+    pub failures: Vec<Failure>,
+}
+
+impl Report {
+    pub fn is_ok(&self) -> bool {
+        self.num_failures == 0
+    }
+}
+
+impl<I: Idx> Ssa<I> {
+    /// Randomized check of the SA and LCP arrays, in `O(n + b log n)` time.
+    /// For each adjacent pair, equality of the LCP-prefixes is checked using a random hash function
+    /// independent of the one used for construction, and the characters following the LCP are compared directly.
+    /// Runs on `threads` threads; `0` uses all cores.
+    pub fn verify_hashed(&self, t: &[u8], threads: usize) -> Report {
+        self.verify_hashed_with(t, EndOfText::Smallest, threads)
+    }
+
+    /// Like `verify_hashed`, with the given order of the end of the text.
+    pub fn verify_hashed_with(&self, t: &[u8], end: EndOfText, threads: usize) -> Report {
+        let n = t.len();
+        let b = self.sa.len();
+        assert_eq!(self.lcp.len(), b - 1);
+        let hasher = RollingHash::new_random(t, sparse_hasher_sampling(n, b));
+
+        let check_pair = |i: usize| -> Option<Failure> {
+            let (a, c, l) = (
+                self.sa[i].as_usize(),
+                self.sa[i + 1].as_usize(),
+                self.lcp[i].as_usize(),
+            );
+            let kind = if a + l > n || c + l > n || hasher.query(a..a + l) != hasher.query(c..c + l)
+            {
+                FailureKind::LcpTooLong
+            } else if a + l == n || c + l == n {
+                // One suffix is a prefix of the other, and sorts first when the end is smallest.
+                if a != c && (a + l == n) == (end == EndOfText::Smallest) {
+                    return None;
+                }
+                FailureKind::Order
+            } else if t[a + l] < t[c + l] {
+                return None;
+            } else if t[a + l] == t[c + l] {
+                FailureKind::LcpTooShort
+            } else {
+                FailureKind::Order
+            };
+            Some(Failure {
+                i,
+                kind,
+                sa: (a, c),
+                lcp: l,
+            })
+        };
+
+        report(b - 1, threads, check_pair)
+    }
+}
+
+/// Run `check_pair` on each of the `checked` adjacent pairs, on `threads` threads.
+pub(crate) fn report(
+    checked: usize,
+    threads: usize,
+    check_pair: impl Fn(usize) -> Option<Failure> + Sync + Send,
+) -> Report {
+    let mut failures: Vec<Failure> = if threads == 1 {
+        (0..checked).filter_map(check_pair).collect()
+    } else {
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build()
+            .unwrap()
+            .install(|| {
+                (0..checked)
+                    .into_par_iter()
+                    .filter_map(check_pair)
+                    .collect()
+            })
+    };
+    let num_failures = failures.len();
+    failures.truncate(MAX_REPORTED);
+    Report {
+        checked,
+        num_failures,
+        failures,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn correct() {
+        for len in [1, 10, 100, 10000] {
+            let t = &(0..len)
+                .map(|_| rand::random::<u8>() % 4)
+                .collect::<Vec<_>>();
+            let idxs = (0..len).collect::<Vec<usize>>();
+            let ssa = Ssa::new(t, &idxs);
+            for threads in [1, 4] {
+                let report = ssa.verify_hashed(t, threads);
+                assert!(report.is_ok(), "{report:?}");
+                assert_eq!(report.checked, len - 1);
+            }
+        }
+        // Repetitive input.
+        let t = &vec![0; 100000];
+        let idxs = (0..t.len()).step_by(7).collect::<Vec<_>>();
+        assert!(Ssa::new(t, &idxs).verify_hashed(t, 0).is_ok());
+    }
+
+    #[test]
+    fn failures() {
+        let t = b"abracadabra";
+        let idxs = (0..t.len()).collect::<Vec<_>>();
+        let ssa = Ssa::new(t, &idxs);
+
+        let mut bad = Ssa {
+            sa: ssa.sa.clone(),
+            lcp: ssa.lcp.clone(),
+        };
+        bad.lcp[3] += 1;
+        let report = bad.verify_hashed(t, 1);
+        assert_eq!(report.num_failures, 1);
+        assert_eq!(report.failures[0].i, 3);
+        assert_eq!(report.failures[0].kind, FailureKind::LcpTooLong);
+
+        let mut bad = Ssa {
+            sa: ssa.sa.clone(),
+            lcp: ssa.lcp.clone(),
+        };
+        // "abra" < "abracadabra" have LCP 4.
+        bad.lcp[1] -= 1;
+        let report = bad.verify_hashed(t, 1);
+        assert_eq!(report.failures[0].kind, FailureKind::LcpTooShort);
+
+        let mut bad = Ssa {
+            sa: ssa.sa.clone(),
+            lcp: ssa.lcp.clone(),
+        };
+        bad.sa.swap(1, 2);
+        let report = bad.verify_hashed(t, 1);
+        assert_eq!(report.failures[0].kind, FailureKind::Order);
+        assert_eq!(report.failures[0].i, 1);
+
+        let mut bad = Ssa {
+            sa: ssa.sa.clone(),
+            lcp: vec![0; ssa.lcp.len()],
+        };
+        bad.sa.reverse();
+        let report = bad.verify_hashed(t, 1);
+        assert_eq!(report.num_failures, t.len() - 1);
+        assert_eq!(report.failures.len(), MAX_REPORTED.min(t.len() - 1));
+    }
+}